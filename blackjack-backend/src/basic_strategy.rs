@@ -0,0 +1,645 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use itertools::Itertools;
+use rocket::serde::json;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blackjack::Action,
+    cards::{Card, HandValue},
+    player::{Hand, HandCategory},
+    rules::{RuleSet, SurrenderRule},
+    strategy::{Strategy, TableView},
+};
+
+// entries of a strategy chart, the usual codes printed on basic strategy cards
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+pub enum ChartAction {
+    #[serde(rename = "H")]
+    Hit,
+    #[serde(rename = "S")]
+    Stand,
+    #[serde(rename = "Dh")]
+    DoubleOrHit,
+    #[serde(rename = "Ds")]
+    DoubleOrStand,
+    #[serde(rename = "P")]
+    Split,
+    #[serde(rename = "Ph")]
+    SplitIfDasOrHit, // split if doubling after splitting is allowed, otherwise hit
+    #[serde(rename = "Rh")]
+    SurrenderOrHit,
+    #[serde(rename = "Rs")]
+    SurrenderOrStand,
+    #[serde(rename = "Rp")]
+    SurrenderOrSplit,
+}
+
+impl FromStr for ChartAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "H" => Ok(ChartAction::Hit),
+            "S" => Ok(ChartAction::Stand),
+            "Dh" => Ok(ChartAction::DoubleOrHit),
+            "Ds" => Ok(ChartAction::DoubleOrStand),
+            "P" => Ok(ChartAction::Split),
+            "Ph" => Ok(ChartAction::SplitIfDasOrHit),
+            "Rh" => Ok(ChartAction::SurrenderOrHit),
+            "Rs" => Ok(ChartAction::SurrenderOrStand),
+            "Rp" => Ok(ChartAction::SurrenderOrSplit),
+            _ => Err(format!(
+                "unknown chart action '{}', expected H, S, Dh, Ds, P, Ph, Rh, Rs or Rp",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ChartAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            ChartAction::Hit => "H",
+            ChartAction::Stand => "S",
+            ChartAction::DoubleOrHit => "Dh",
+            ChartAction::DoubleOrStand => "Ds",
+            ChartAction::Split => "P",
+            ChartAction::SplitIfDasOrHit => "Ph",
+            ChartAction::SurrenderOrHit => "Rh",
+            ChartAction::SurrenderOrStand => "Rs",
+            ChartAction::SurrenderOrSplit => "Rp",
+        };
+        write!(f, "{}", code)
+    }
+}
+
+// every table has one column per dealer up card, from 2 to ace
+pub const UP_CARDS: usize = 10;
+pub const HARD_TOTALS: std::ops::RangeInclusive<i8> = 5..=21;
+pub const SOFT_TOTALS: std::ops::RangeInclusive<i8> = 12..=21;
+pub const PAIRS: std::ops::RangeInclusive<i8> = 2..=11; // numeric value of the paired cards, 11 are aces
+
+// column headers of chart files
+const UP_CARD_LABELS: [&str; UP_CARDS] = ["2", "3", "4", "5", "6", "7", "8", "9", "T", "A"];
+
+// tens and aces are written as T and A
+fn rank_label(value: i8) -> String {
+    match value {
+        11 | 1 => String::from("A"),
+        10 => String::from("T"),
+        _ => value.to_string(),
+    }
+}
+
+// labels of the rows of a table in chart files: totals for hard and soft hands, the paired rank for pairs
+fn row_labels(table: &str) -> Vec<String> {
+    match table {
+        "hard" => HARD_TOTALS.map(|total| total.to_string()).collect(),
+        "soft" => SOFT_TOTALS.map(|total| total.to_string()).collect(),
+        _ => PAIRS.map(rank_label).collect(),
+    }
+}
+
+type ChartRow = [ChartAction; UP_CARDS];
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct StrategyChart {
+    pub hard: Vec<ChartRow>,  // one row per total in HARD_TOTALS
+    pub soft: Vec<ChartRow>,  // one row per total in SOFT_TOTALS
+    pub pairs: Vec<ChartRow>, // one row per pair in PAIRS
+}
+
+impl StrategyChart {
+    // basic strategy for the given table rules, based on the dealer rules, deck count, DAS and surrender
+    pub fn for_rules(rules: &RuleSet) -> Self {
+        let generator = ChartGenerator { rules: *rules };
+        let rows = |totals: std::ops::RangeInclusive<i8>, entry: &dyn Fn(i8, i8) -> ChartAction| {
+            totals
+                .map(|total| std::array::from_fn(|column| entry(total, column as i8 + 2)))
+                .collect()
+        };
+
+        StrategyChart {
+            hard: rows(HARD_TOTALS, &|total, up| generator.hard(total, up)),
+            soft: rows(SOFT_TOTALS, &|total, up| generator.soft(total, up)),
+            pairs: rows(PAIRS, &|pair, up| generator.pair(pair, up)),
+        }
+    }
+
+    // chart entry for a hand, pairs are only looked up if the hand can be split
+    pub fn entry(&self, hand: &Hand, up_card: &Card, can_split: bool) -> ChartAction {
+        let column = (up_card.numeric_value - 2) as usize;
+
+        if hand.category(can_split) == HandCategory::Pair {
+            let row = (hand.cards[0].numeric_value - PAIRS.start()) as usize;
+            return self.pairs[row][column];
+        }
+        self.total_entry(&hand.evaluate(), column)
+    }
+
+    fn total_entry(&self, value: &HandValue, column: usize) -> ChartAction {
+        if value.is_soft {
+            let total = value.total.clamp(*SOFT_TOTALS.start(), *SOFT_TOTALS.end());
+            self.soft[(total - SOFT_TOTALS.start()) as usize][column]
+        } else {
+            let total = value.total.clamp(*HARD_TOTALS.start(), *HARD_TOTALS.end());
+            self.hard[(total - HARD_TOTALS.start()) as usize][column]
+        }
+    }
+
+    // the action to play at the table
+    pub fn recommend(&self, view: &TableView) -> Action {
+        match (view.hand(), view.dealer_up_card) {
+            (Some(hand), Some(up_card)) => self.action(hand, up_card, &view.rules, &|action| {
+                view.is_allowed(action)
+            }),
+            // basic strategy never takes insurance, even money included
+            _ => Action::DeclineInsurance,
+        }
+    }
+
+    // the chart entry of a hand, falls back to the second choice of the entry if the first one is not allowed
+    pub fn action(
+        &self,
+        hand: &Hand,
+        up_card: &Card,
+        rules: &RuleSet,
+        allowed: &dyn Fn(Action) -> bool,
+    ) -> Action {
+        let hit = if allowed(Action::Hit) {
+            Action::Hit
+        } else {
+            Action::Stand
+        };
+        let split = if allowed(Action::Split) {
+            Action::Split
+        } else {
+            // pairs are only looked up if they can be split, this only happens with imported charts
+            self.resolve_total(hand, up_card, hit, allowed)
+        };
+
+        match self.entry(hand, up_card, allowed(Action::Split)) {
+            ChartAction::Hit => hit,
+            ChartAction::Stand => Action::Stand,
+            ChartAction::DoubleOrHit if allowed(Action::Double) => Action::Double,
+            ChartAction::DoubleOrHit => hit,
+            ChartAction::DoubleOrStand if allowed(Action::Double) => Action::Double,
+            ChartAction::DoubleOrStand => Action::Stand,
+            ChartAction::Split => split,
+            ChartAction::SplitIfDasOrHit if rules.double_after_split => split,
+            ChartAction::SplitIfDasOrHit => hit,
+            ChartAction::SurrenderOrHit
+            | ChartAction::SurrenderOrStand
+            | ChartAction::SurrenderOrSplit
+                if allowed(Action::Surrender) =>
+            {
+                Action::Surrender
+            }
+            ChartAction::SurrenderOrHit => hit,
+            ChartAction::SurrenderOrStand => Action::Stand,
+            ChartAction::SurrenderOrSplit => split,
+        }
+    }
+
+    // play a hand by its total if it can not be split
+    fn resolve_total(
+        &self,
+        hand: &Hand,
+        up_card: &Card,
+        hit: Action,
+        allowed: &dyn Fn(Action) -> bool,
+    ) -> Action {
+        let column = (up_card.numeric_value - 2) as usize;
+        match self.total_entry(&hand.evaluate(), column) {
+            ChartAction::Stand | ChartAction::SurrenderOrStand => Action::Stand,
+            ChartAction::DoubleOrHit | ChartAction::DoubleOrStand if allowed(Action::Double) => {
+                Action::Double
+            }
+            ChartAction::DoubleOrStand => Action::Stand,
+            _ => hit,
+        }
+    }
+}
+
+// charts are stored as JSON (the serialized chart) or as a CSV grid with one row per hand:
+// `table,hand,2,3,4,5,6,7,8,9,T,A` followed by rows like `hard,12,H,H,S,S,S,H,H,H,H,H`
+impl StrategyChart {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("could not read chart file '{}': {}", path, err))?;
+        let chart = match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("csv") => Self::from_csv(&content),
+            Some("json") => Self::from_json(&content),
+            _ => Err(String::from("chart files have to end in .csv or .json")),
+        };
+        chart.map_err(|err| format!("invalid chart file '{}': {}", path, err))
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, String> {
+        let chart: StrategyChart = json::from_str(content).map_err(|err| err.to_string())?;
+        chart.validate()?;
+        Ok(chart)
+    }
+
+    pub fn to_json(&self) -> String {
+        json::to_string(self).expect("a chart can always be serialized")
+    }
+
+    pub fn from_csv(content: &str) -> Result<Self, String> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        let header = format!("table,hand,{}", UP_CARD_LABELS.join(","));
+        match lines.next() {
+            Some((_, line)) if line.split(',').map(str::trim).join(",") == header => {}
+            _ => return Err(format!("the first line has to be '{}'", header)),
+        }
+
+        let tables = ["hard", "soft", "pair"];
+        let mut rows: Vec<Vec<Option<ChartRow>>> = tables
+            .iter()
+            .map(|table| vec![None; row_labels(table).len()])
+            .collect();
+
+        for (number, line) in lines {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() != UP_CARDS + 2 {
+                return Err(error(format!(
+                    "expected {} fields, got {}",
+                    UP_CARDS + 2,
+                    fields.len()
+                )));
+            }
+
+            let table = tables
+                .iter()
+                .position(|table| *table == fields[0])
+                .ok_or_else(|| {
+                    error(format!(
+                        "unknown table '{}', expected hard, soft or pair",
+                        fields[0]
+                    ))
+                })?;
+            let row = row_labels(tables[table])
+                .iter()
+                .position(|label| label == fields[1])
+                .ok_or_else(|| {
+                    error(format!(
+                        "the {} table has no row '{}'",
+                        tables[table], fields[1]
+                    ))
+                })?;
+            if rows[table][row].is_some() {
+                return Err(error(format!(
+                    "{} {} is defined twice",
+                    tables[table], fields[1]
+                )));
+            }
+
+            let mut entries = [ChartAction::Stand; UP_CARDS];
+            for (entry, field) in entries.iter_mut().zip(&fields[2..]) {
+                *entry = field.parse().map_err(error)?;
+            }
+            rows[table][row] = Some(entries);
+        }
+
+        // every hand of every table has to be defined
+        let complete = |table: usize| {
+            rows[table]
+                .iter()
+                .zip(row_labels(tables[table]))
+                .map(|(row, label)| {
+                    row.ok_or_else(|| format!("{} {} is missing", tables[table], label))
+                })
+                .collect::<Result<Vec<ChartRow>, String>>()
+        };
+        let chart = StrategyChart {
+            hard: complete(0)?,
+            soft: complete(1)?,
+            pairs: complete(2)?,
+        };
+        chart.validate()?;
+        Ok(chart)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("table,hand,{}\n", UP_CARD_LABELS.join(","));
+        for (table, rows) in [
+            ("hard", &self.hard),
+            ("soft", &self.soft),
+            ("pair", &self.pairs),
+        ] {
+            for (label, row) in row_labels(table).iter().zip(rows) {
+                csv += &format!("{},{},{}\n", table, label, row.iter().join(","));
+            }
+        }
+        csv
+    }
+
+    // every table needs one row per hand, and only pairs can be split
+    pub fn validate(&self) -> Result<(), String> {
+        for (table, rows) in [
+            ("hard", &self.hard),
+            ("soft", &self.soft),
+            ("pair", &self.pairs),
+        ] {
+            let expected = row_labels(table).len();
+            if rows.len() != expected {
+                return Err(format!(
+                    "the {} table needs {} rows, got {}",
+                    table,
+                    expected,
+                    rows.len()
+                ));
+            }
+        }
+
+        for (table, rows) in [("hard", &self.hard), ("soft", &self.soft)] {
+            for (label, row) in row_labels(table).iter().zip(rows) {
+                let split = row.iter().position(|entry| {
+                    matches!(
+                        entry,
+                        ChartAction::Split
+                            | ChartAction::SplitIfDasOrHit
+                            | ChartAction::SurrenderOrSplit
+                    )
+                });
+                if let Some(column) = split {
+                    return Err(format!(
+                        "{} {} against {}: {} is only possible for pairs",
+                        table, label, UP_CARD_LABELS[column], row[column]
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for StrategyChart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = "      2   3   4   5   6   7   8   9   T   A";
+        let mut table = |title: &str, labels: Vec<String>, rows: &[ChartRow]| {
+            writeln!(f, "{}", title)?;
+            writeln!(f, "{}", header)?;
+            for (label, row) in labels.iter().zip(rows) {
+                let entries = row
+                    .iter()
+                    .map(|entry| format!("{:<3}", entry.to_string()))
+                    .join(" ");
+                writeln!(f, "{:<5} {}", label, entries.trim_end())?;
+            }
+            writeln!(f)
+        };
+
+        table("Hard totals", row_labels("hard"), &self.hard)?;
+        table(
+            "Soft totals",
+            SOFT_TOTALS
+                .map(|total| format!("A,{}", rank_label(total - 11)))
+                .collect(),
+            &self.soft,
+        )?;
+        table(
+            "Pairs",
+            PAIRS
+                .map(|pair| format!("{},{}", rank_label(pair), rank_label(pair)))
+                .collect(),
+            &self.pairs,
+        )
+    }
+}
+
+// the chart entries as functions of the hand and the dealers up card (2 to 11 for an ace)
+struct ChartGenerator {
+    rules: RuleSet,
+}
+
+impl ChartGenerator {
+    fn hard(&self, total: i8, up: i8) -> ChartAction {
+        use ChartAction::*;
+
+        let decks = self.rules.number_of_decks;
+        let h17 = self.rules.dealer_hits_soft_17;
+        // without a hole card, the player loses his double against a dealer blackjack
+        let no_hole_card = !self.rules.dealer_peeks;
+
+        let action = match total {
+            8 if decks == 1 && (5..=6).contains(&up) => DoubleOrHit,
+            ..=8 => Hit,
+            9 if (3..=6).contains(&up) || (decks <= 2 && up == 2) => DoubleOrHit,
+            10 if up <= 9 => DoubleOrHit,
+            11 if no_hole_card && up >= 10 => Hit,
+            11 if up <= 10 || h17 || decks <= 2 => DoubleOrHit,
+            12 if (4..=6).contains(&up) => Stand,
+            13..=16 if up <= 6 => Stand,
+            9..=16 => Hit,
+            _ => Stand,
+        };
+
+        if self.surrenders(total, up) {
+            return if action == Stand {
+                SurrenderOrStand
+            } else {
+                SurrenderOrHit
+            };
+        }
+        action
+    }
+
+    fn surrenders(&self, total: i8, up: i8) -> bool {
+        let decks = self.rules.number_of_decks;
+        let h17 = self.rules.dealer_hits_soft_17;
+
+        match self.rules.surrender {
+            SurrenderRule::None => false,
+            SurrenderRule::Late => match (total, up) {
+                (16, 10 | 11) | (15, 10) => true,
+                (16, 9) => decks >= 4,
+                (15, 11) | (17, 11) => h17,
+                _ => false,
+            },
+            // before the dealer checked for blackjack, even weak hands are given up against an ace or a ten
+            SurrenderRule::Early => matches!((total, up), (5..=7 | 12..=17, 11) | (14..=16, 10)),
+        }
+    }
+
+    fn soft(&self, total: i8, up: i8) -> ChartAction {
+        use ChartAction::*;
+
+        let decks = self.rules.number_of_decks;
+        let h17 = self.rules.dealer_hits_soft_17;
+
+        match total {
+            13 | 14 if (5..=6).contains(&up) || (decks == 1 && up == 4) => DoubleOrHit,
+            15 | 16 if (4..=6).contains(&up) => DoubleOrHit,
+            17 if (3..=6).contains(&up) || (decks == 1 && up == 2) => DoubleOrHit,
+            ..=17 => Hit,
+            18 => match up {
+                2 if h17 => DoubleOrStand,
+                3..=6 => DoubleOrStand,
+                2 | 7 | 8 => Stand,
+                11 if decks == 1 && !h17 => Stand,
+                _ => Hit,
+            },
+            19 if up == 6 && (h17 || decks == 1) => DoubleOrStand,
+            _ => Stand,
+        }
+    }
+
+    fn pair(&self, pair: i8, up: i8) -> ChartAction {
+        use ChartAction::*;
+
+        let das = self.rules.double_after_split;
+        let decks = self.rules.number_of_decks;
+        let h17 = self.rules.dealer_hits_soft_17;
+        let no_hole_card = !self.rules.dealer_peeks;
+        // pairs which are not split are played like any other hand with the same total
+        let not_split = || {
+            if pair == 11 {
+                self.soft(12, up)
+            } else {
+                self.hard(pair * 2, up)
+            }
+        };
+        // splits which only pay off if the split hands can be doubled
+        let split_with_das = || if das { SplitIfDasOrHit } else { not_split() };
+
+        match pair {
+            2 | 3 if (4..=7).contains(&up) => Split,
+            2 | 3 if up <= 3 || (pair == 3 && decks == 1 && up == 8) => split_with_das(),
+            4 if (5..=6).contains(&up) => split_with_das(),
+            6 if (3..=6).contains(&up) => Split,
+            6 if up == 2 || (decks == 1 && up == 7) => split_with_das(),
+            7 if up <= 7 || (decks == 1 && up == 8) => Split,
+            8 if no_hole_card && up >= 10 => not_split(),
+            8 if up == 11
+                && (self.rules.surrender == SurrenderRule::Early
+                    || (self.rules.surrender == SurrenderRule::Late && h17)) =>
+            {
+                SurrenderOrSplit
+            }
+            8 => Split,
+            9 if up <= 9 && up != 7 => Split,
+            11 if no_hole_card && up == 11 => not_split(),
+            11 => Split,
+            _ => not_split(),
+        }
+    }
+}
+
+// plays by the basic strategy chart of the table rules
+pub struct BasicStrategy {
+    pub bet: i32,
+    pub chart: StrategyChart,
+}
+
+impl BasicStrategy {
+    pub fn new(bet: i32, rules: &RuleSet) -> Self {
+        BasicStrategy {
+            bet,
+            chart: StrategyChart::for_rules(rules),
+        }
+    }
+}
+
+impl Strategy for BasicStrategy {
+    fn bet(&mut self, _view: &TableView) -> Option<i32> {
+        Some(self.bet)
+    }
+
+    fn decide(&mut self, view: &TableView) -> Action {
+        self.chart.recommend(view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::SurrenderRule;
+
+    fn chart() -> StrategyChart {
+        StrategyChart::for_rules(&RuleSet::default())
+    }
+
+    // the csv of the generated chart with one line replaced
+    fn csv_with(table_and_hand: &str, line: Option<&str>) -> String {
+        chart()
+            .to_csv()
+            .lines()
+            .filter_map(|original| {
+                if original.starts_with(&format!("{},", table_and_hand)) {
+                    line
+                } else {
+                    Some(original)
+                }
+            })
+            .join("\n")
+    }
+
+    #[test]
+    fn csv_and_json_round_trip() {
+        let casino = RuleSet {
+            number_of_decks: 6,
+            dealer_hits_soft_17: true,
+            double_after_split: false,
+            surrender: SurrenderRule::Late,
+            ..RuleSet::default()
+        };
+        for rules in [RuleSet::default(), casino] {
+            let chart = StrategyChart::for_rules(&rules);
+            assert_eq!(StrategyChart::from_csv(&chart.to_csv()), Ok(chart.clone()));
+            assert_eq!(StrategyChart::from_json(&chart.to_json()), Ok(chart));
+        }
+    }
+
+    #[test]
+    fn rejects_a_missing_row() {
+        let err = StrategyChart::from_csv(&csv_with("soft,13", None)).unwrap_err();
+        assert!(err.contains("soft 13 is missing"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_a_duplicate_row() {
+        let mut csv = chart().to_csv();
+        csv += "hard,12,H,H,S,S,S,H,H,H,H,H\n";
+        let err = StrategyChart::from_csv(&csv).unwrap_err();
+        assert!(err.contains("hard 12 is defined twice"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_an_unknown_code() {
+        let csv = csv_with("hard,12", Some("hard,12,H,H,S,S,X,H,H,H,H,H"));
+        let err = StrategyChart::from_csv(&csv).unwrap_err();
+        assert!(err.contains("unknown chart action 'X'"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_a_split_outside_the_pair_table() {
+        let csv = csv_with("hard,16", Some("hard,16,S,S,S,S,S,H,H,P,H,H"));
+        let err = StrategyChart::from_csv(&csv).unwrap_err();
+        assert!(err.contains("hard 16 against 9"), "{}", err);
+
+        let mut chart = chart();
+        chart.soft[0][0] = ChartAction::Split;
+        assert!(StrategyChart::from_json(&chart.to_json()).is_err());
+    }
+
+    #[test]
+    fn rejects_a_wrong_header_or_row_count() {
+        let csv = chart().to_csv().replacen("table,hand", "table,total", 1);
+        assert!(StrategyChart::from_csv(&csv).is_err());
+
+        let mut chart = chart();
+        chart.hard.pop();
+        let err = StrategyChart::from_json(&chart.to_json()).unwrap_err();
+        assert!(err.contains("the hard table needs"), "{}", err);
+    }
+}
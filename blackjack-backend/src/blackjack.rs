@@ -1,271 +1,1143 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
 use serde::Serialize;
 
 use crate::{
-    cards::{self, Card},
+    basic_strategy::StrategyChart,
+    cards::{Card, Value},
     dealer::Dealer,
-    player::Player,
+    player::{Hand, HandCategory, HandOutcome, Player},
+    rules::{RuleSet, SurrenderRule},
+    shoe::Shoe,
+    strategy::TableView,
 };
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, PartialEq, Serialize, Clone, Copy)]
 pub enum GameStatus {
     Initalized,
     Ongoing,
     PlayerWon,
+    PlayerBlackjack,
     PlayerFinished,
     DealerWon,
     Draw,
+    InsuranceOffered,
+    Surrendered,
 }
 
+#[derive(Debug, PartialEq, Serialize, Clone, Copy)]
 pub enum Action {
     Hit,
     Stand,
     Double,
     Split,
+    TakeInsurance,
+    DeclineInsurance,
+    Surrender,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Hit" => Ok(Action::Hit),
+            "Stand" => Ok(Action::Stand),
+            "Double" => Ok(Action::Double),
+            "Split" => Ok(Action::Split),
+            "TakeInsurance" => Ok(Action::TakeInsurance),
+            "DeclineInsurance" => Ok(Action::DeclineInsurance),
+            "Surrender" => Ok(Action::Surrender),
+            _ => Err(format!("unknown action '{}'", s)),
+        }
+    }
+}
+
+// the phases a round goes through, every method of the game is only allowed in some of them
+#[derive(Debug, PartialEq, Serialize, Clone, Copy)]
+pub enum Phase {
+    Betting,
+    InsuranceOffered,
+    PlayerTurn(usize), // index of the hand which is played
+    DealerTurn,
+    Settled,
 }
 
-#[derive(Debug, PartialEq, Serialize)]
-pub struct GameCliVersion<'player, 'shoe> {
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum GameError {
+    WrongPhase(Phase),     // the request does not fit the current phase of the round
+    IllegalAction(Action), // the action is not allowed on the active hand
+    InvalidBet(i32),
+    InsufficientBalance { balance: i32, required: i32 },
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::WrongPhase(phase) => match phase {
+                Phase::Betting => write!(f, "no round is played, place a bet first"),
+                Phase::InsuranceOffered => write!(f, "decide on insurance first"),
+                Phase::PlayerTurn(_) => write!(f, "the player has to finish his hands first"),
+                Phase::DealerTurn => write!(f, "it is the dealers turn"),
+                Phase::Settled => write!(f, "the round is over, start the next one"),
+            },
+            GameError::IllegalAction(action) => {
+                write!(f, "{:?} is not allowed on this hand", action)
+            }
+            GameError::InvalidBet(bet) => write!(f, "bets have to be positive, got {}", bet),
+            GameError::InsufficientBalance { balance, required } => write!(
+                f,
+                "insufficient balance: {} required, but only {} left",
+                required, balance
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+// a decision in training mode which differs from basic strategy
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct Mistake {
+    pub round: i32,
+    pub category: HandCategory,
+    pub cards: Vec<Card>, // the hand before the decision
+    pub dealer_up_card: Card,
+    pub action: Action,
+    pub correct_action: Action,
+}
+
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct Game {
     pub dealer: Dealer,
-    pub player: &'player mut Player,
-    pub bets: i32,
-    pub shoe: &'shoe mut Vec<Card>,
-    pub number_of_decks: i8,
+    pub player: Player,
+    pub phase: Phase,
+    pub peek_pending: bool, // dealer waits with checking for blackjack until the player decided on early surrender
+    pub shoe: Shoe,
+    pub rules: RuleSet,
+    pub training: bool, // every decision is compared with basic strategy
+    pub mistakes: Vec<Mistake>,
 }
 
-// this implementation was used for the cli version of the game
-// it uses &mut Player instead of Player; still here to easily switch back to cli version for debugging
-impl<'player, 'shoe> GameCliVersion<'player, 'shoe> {
-    pub fn new(
-        player: &'player mut Player,
-        shoe: &'shoe mut Vec<Card>,
-        number_of_decks: i8,
-    ) -> Self {
-        GameCliVersion {
+impl Game {
+    // the seed of the shoe, a game with the same seed and the same decisions plays out the same
+    pub fn new(player: Player, rules: RuleSet, seed: Option<u64>) -> Self {
+        Game {
             dealer: Dealer::new(),
             player,
-            bets: 0,
-            shoe,
-            number_of_decks,
+            phase: Phase::Betting,
+            peek_pending: false,
+            shoe: Shoe::new(&rules, seed),
+            rules,
+            training: false,
+            mistakes: Vec::new(),
         }
     }
 
-    pub fn start(&mut self, bets: i32) -> GameStatus {
+    // deal from another shoe from the next round on, e.g. a stacked one; the count starts over
+    pub fn replace_shoe(&mut self, shoe: Shoe) -> Result<(), GameError> {
+        if self.phase != Phase::Betting {
+            return Err(GameError::WrongPhase(self.phase));
+        }
+        self.shoe = shoe;
+        self.player.stats.reset_card_count(&self.shoe);
+        Ok(())
+    }
+
+    // total amount betted on all hands of the player
+    pub fn bets(&self) -> i32 {
+        self.player.hands.iter().map(|hand| hand.bet).sum()
+    }
+
+    // index of the hand the player is deciding on
+    pub fn active_hand(&self) -> Option<usize> {
+        match self.phase {
+            Phase::PlayerTurn(index) => Some(index),
+            _ => None,
+        }
+    }
+
+    // status of the round as reported to the player
+    pub fn status(&self) -> GameStatus {
+        match self.phase {
+            Phase::Betting => GameStatus::Initalized,
+            Phase::InsuranceOffered => GameStatus::InsuranceOffered,
+            Phase::PlayerTurn(_) => GameStatus::Ongoing,
+            Phase::DealerTurn => GameStatus::PlayerFinished,
+            Phase::Settled => self.round_status(),
+        }
+    }
+
+    pub fn start(&mut self, bets: i32) -> Result<GameStatus, GameError> {
+        if self.phase != Phase::Betting {
+            return Err(GameError::WrongPhase(self.phase));
+        }
+        if bets <= 0 {
+            return Err(GameError::InvalidBet(bets));
+        }
+        if bets > self.player.balance {
+            return Err(GameError::InsufficientBalance {
+                balance: self.player.balance,
+                required: bets,
+            });
+        }
+
         // set bets
         self.player.balance -= bets;
-        self.bets = bets;
+        self.player.hands = vec![Hand::new(bets)];
 
         // update betting stats
         self.player.stats.update_average_bet(bets);
         self.player.stats.update_matches_played();
 
-        // dealer draws his first two cards
+        // deal in casino order: player, dealer (face up), player, dealer (face down)
+        // without a hole card, the dealer gets his second card after the player finished
+        self.player.draw_card(&mut self.shoe, 0);
         self.dealer
-            .inital_draw(&mut self.shoe, self.number_of_decks, self.player);
+            .draw_card(&mut self.shoe, &mut self.player, true);
+        self.player.draw_card(&mut self.shoe, 0);
+        if self.rules.dealer_peeks {
+            self.dealer
+                .draw_card(&mut self.shoe, &mut self.player, false);
+        }
 
-        if cards::hand_value(&self.dealer.hand) == 21 {
-            GameStatus::DealerWon
+        // if the dealer shows an ace, the player decides on insurance before the dealer checks for blackjack
+        if self.rules.insurance && self.dealer.up_card().unwrap().value == Value::Ace {
+            self.player.stats.update_insurance_offered();
+            return Ok(self.enter(Phase::InsuranceOffered));
         }
-        // player draws his first card
-        else {
-            self.player.draw_card(&mut self.shoe, self.number_of_decks);
-            GameStatus::Ongoing
+
+        let phase = self.peek();
+        Ok(self.enter(phase))
+    }
+
+    // move on to the next phase and settle the round as soon as it is decided
+    fn enter(&mut self, phase: Phase) -> GameStatus {
+        self.phase = phase;
+        if phase == Phase::Settled {
+            self.settle();
         }
+        self.status()
     }
 
-    pub fn play_action(&mut self, action: Action) -> GameStatus {
-        match action {
-            Action::Hit => {
-                self.player.draw_card(&mut self.shoe, self.number_of_decks);
-                if cards::hand_value(&self.player.hand) > 21 {
-                    GameStatus::DealerWon
-                } else if cards::hand_value(&self.player.hand) == 21 {
-                    GameStatus::PlayerWon
-                } else {
-                    GameStatus::Ongoing
+    // insurance is half the bet (rounded down) and pays 2:1; with a natural, the player can take even money instead
+    fn decide_insurance(&mut self, take_insurance: bool) -> Result<Phase, GameError> {
+        if take_insurance {
+            let hand = &self.player.hands[0];
+            if !hand.is_blackjack() && hand.bet / 2 == 0 {
+                return Err(GameError::IllegalAction(Action::TakeInsurance));
+            }
+            if !hand.is_blackjack() && hand.bet / 2 > self.player.balance {
+                return Err(GameError::InsufficientBalance {
+                    balance: self.player.balance,
+                    required: hand.bet / 2,
+                });
+            }
+
+            self.player.stats.update_insurance_taken();
+            let hand = &mut self.player.hands[0];
+            if hand.is_blackjack() {
+                hand.even_money = true;
+                hand.outcome = Some(HandOutcome::Won);
+                hand.stood = true;
+            } else {
+                hand.insurance = hand.bet / 2;
+                self.player.balance -= hand.insurance;
+            }
+        }
+
+        Ok(self.peek())
+    }
+
+    // the dealer checks his hole card for a natural; with early surrender this waits for the players first decision
+    fn peek(&mut self) -> Phase {
+        let hand = &self.player.hands[0];
+        if self.rules.dealer_peeks
+            && self.rules.surrender == SurrenderRule::Early
+            && self.dealer.up_card().unwrap().numeric_value >= 10
+            && !hand.stood
+            && !hand.is_blackjack()
+        {
+            self.peek_pending = true;
+            return Phase::PlayerTurn(0);
+        }
+
+        // without a hole card, insurance is decided once the dealer draws his second card
+        if self.rules.dealer_peeks {
+            self.settle_insurance();
+        }
+        self.check_naturals()
+    }
+
+    // the player counts the hole card once the dealer unveils it
+    fn count_hole_card(&mut self) {
+        if let Some(hole_card) = self.dealer.hole_card() {
+            self.player.stats.update_card_count(hole_card, &self.shoe);
+        }
+    }
+
+    // pay out the insurance bet once the dealers second card is known
+    fn settle_insurance(&mut self) {
+        let dealer_has_blackjack = self.dealer.evaluate().is_blackjack;
+        let hand = &mut self.player.hands[0];
+
+        let profit = if hand.even_money {
+            // compared to declining, even money pays off if the dealer has a natural
+            if dealer_has_blackjack {
+                hand.bet
+            } else {
+                hand.bet - self.rules.blackjack_payout.winnings(hand.bet)
+            }
+        } else if hand.insurance > 0 {
+            let insurance = hand.insurance;
+            hand.insurance = 0;
+            if dealer_has_blackjack {
+                self.player.balance += insurance * 3;
+                insurance * 2
+            } else {
+                -insurance
+            }
+        } else {
+            return;
+        };
+
+        self.player.stats.update_insurance_result(profit);
+    }
+
+    // resolve the round right away if the player or the dealer has a natural
+    fn check_naturals(&mut self) -> Phase {
+        let player_has_blackjack = self.player.hands[0].is_blackjack();
+        let dealer_has_blackjack = self.dealer.evaluate().is_blackjack;
+
+        // even money was already paid, the round is over for the player
+        if self.player.hands[0].even_money {
+            if dealer_has_blackjack {
+                self.count_hole_card();
+            }
+            return Phase::Settled;
+        }
+
+        // without a hole card, a player natural against an ace or ten has to wait for the dealers second card
+        if player_has_blackjack
+            && !self.rules.dealer_peeks
+            && self.dealer.up_card().unwrap().numeric_value >= 10
+        {
+            self.player.hands[0].stood = true;
+            return self.check_dealer_blackjack();
+        }
+
+        if dealer_has_blackjack {
+            // dealer unveils his second card
+            self.count_hole_card();
+        }
+
+        let outcome = match (player_has_blackjack, dealer_has_blackjack) {
+            (true, true) => HandOutcome::Push,
+            (true, false) => HandOutcome::Blackjack,
+            (false, true) => HandOutcome::Lost,
+            (false, false) => return Phase::PlayerTurn(0),
+        };
+
+        let hand = &mut self.player.hands[0];
+        hand.outcome = Some(outcome);
+        hand.stood = true;
+        Phase::Settled
+    }
+
+    // all actions are played on the active hand
+    pub fn play_action(&mut self, action: Action) -> Result<GameStatus, GameError> {
+        let phase = match (self.phase, action) {
+            // the insurance decision comes before everything else
+            (Phase::InsuranceOffered, Action::TakeInsurance) => self.decide_insurance(true)?,
+            (Phase::InsuranceOffered, Action::DeclineInsurance) => self.decide_insurance(false)?,
+            // insurance is only possible right after the cards were dealt
+            (Phase::PlayerTurn(_), Action::TakeInsurance | Action::DeclineInsurance) => {
+                return Err(GameError::IllegalAction(action))
+            }
+            (Phase::PlayerTurn(index), _) => {
+                let decision = self.judge(index, action);
+                let phase = self.play_hand(index, action)?;
+                // only legal decisions are counted
+                if let Some(decision) = decision {
+                    let correct = decision.action == decision.correct_action;
+                    self.player.stats.update_decisions(
+                        decision.category,
+                        &decision.dealer_up_card,
+                        correct,
+                    );
+                    if !correct {
+                        self.mistakes.push(decision);
+                    }
                 }
+                phase
+            }
+            (phase, _) => return Err(GameError::WrongPhase(phase)),
+        };
+
+        Ok(self.enter(phase))
+    }
+
+    // compare the decision with basic strategy, if the player is training
+    fn judge(&self, index: usize, action: Action) -> Option<Mistake> {
+        if !self.training {
+            return None;
+        }
+
+        let hand = &self.player.hands[index];
+        let correct_action = StrategyChart::for_rules(&self.rules).recommend(&TableView::new(self));
+        Some(Mistake {
+            round: self.player.stats.matches_played(),
+            category: hand.category(self.can_split()),
+            cards: hand.cards.clone(),
+            dealer_up_card: self.dealer.up_card()?.clone(),
+            action,
+            correct_action,
+        })
+    }
+
+    fn play_hand(&mut self, index: usize, action: Action) -> Result<Phase, GameError> {
+        let allowed = match action {
+            Action::Hit => self.can_hit(),
+            Action::Double => self.can_double(),
+            Action::Split => self.can_split(),
+            Action::Surrender => self.can_surrender(),
+            _ => true,
+        };
+        if !allowed {
+            return Err(GameError::IllegalAction(action));
+        }
+
+        // any decision except early surrender lets the dealer check for blackjack first
+        if self.peek_pending && action != Action::Surrender {
+            self.peek_pending = false;
+            self.settle_insurance();
+            let phase = self.check_naturals();
+            if phase != Phase::PlayerTurn(0) {
+                return Ok(phase);
             }
-            Action::Stand => GameStatus::PlayerFinished,
+        }
+
+        match action {
+            Action::Hit => self.player.draw_card(&mut self.shoe, index),
+            Action::Stand => self.player.hands[index].stood = true,
             Action::Double => {
                 // double bets
-                self.player.balance -= self.bets;
-                self.bets += self.bets;
+                let hand = &mut self.player.hands[index];
+                self.player.balance -= hand.bet;
+                hand.bet += hand.bet;
+                hand.doubled = true;
+                let new_bet = hand.bet;
 
-                self.player.draw_card(&mut self.shoe, self.number_of_decks);
+                self.player.draw_card(&mut self.shoe, index);
+                self.player.hands[index].stood = true;
 
                 // update stats
-                self.player.stats.update_average_bet(self.bets);
+                self.player.stats.update_average_bet(new_bet);
                 self.player.stats.update_times_doubled();
+            }
+            Action::Split => {
+                // the second card starts a new hand with the same bet
+                let bet = self.player.hands[index].bet;
+                self.player.balance -= bet;
+                let mut new_hand = Hand::new(bet);
+                new_hand.split = true;
+                new_hand
+                    .cards
+                    .push(self.player.hands[index].cards.pop().unwrap());
+                self.player.hands[index].split = true;
+                self.player.hands.insert(index + 1, new_hand);
 
-                // check if player won
-                if cards::hand_value(&self.player.hand) > 21 {
-                    GameStatus::DealerWon
-                } else if cards::hand_value(&self.player.hand) == 21 {
-                    GameStatus::PlayerWon
-                } else {
-                    GameStatus::PlayerFinished
+                // update stats
+                self.player.stats.update_average_bet(bet);
+                self.player.stats.update_times_split();
+
+                // the first hand gets its second card right away, the new one once it is played
+                self.player.draw_card(&mut self.shoe, index);
+            }
+            Action::Surrender => {
+                // half of the bet is refunded at the end of the round
+                let hand = &mut self.player.hands[index];
+                hand.stood = true;
+                hand.outcome = Some(HandOutcome::Surrendered);
+                self.player.stats.update_times_surrendered();
+
+                if self.peek_pending {
+                    // early surrender: the dealer checks for blackjack afterwards to settle the insurance
+                    self.peek_pending = false;
+                    self.settle_insurance();
+                    if self.dealer.evaluate().is_blackjack {
+                        self.count_hole_card();
+                    }
+                } else if !self.rules.dealer_peeks
+                    && self.rules.surrender == SurrenderRule::Late
+                    && self.dealer.up_card().unwrap().numeric_value >= 10
+                {
+                    // without a hole card, a late surrender only counts if the dealer has no natural
+                    return Ok(self.check_dealer_blackjack());
                 }
+                return Ok(Phase::Settled);
             }
-            Action::Split => unimplemented!(),
+            Action::TakeInsurance | Action::DeclineInsurance => unreachable!(),
         }
+
+        self.check_hand(index);
+        Ok(self.next_hand(index))
     }
 
-    pub fn play_dealers_turn(&mut self) -> GameStatus {
-        // dealer unviels his second card
-        self.player
-            .stats
-            .update_card_count(self.dealer.hand.last().unwrap().clone());
+    // the hand which is played, if the player is at turn
+    fn active(&self) -> Option<&Hand> {
+        self.active_hand()
+            .and_then(|index| self.player.hands.get(index))
+    }
 
-        while cards::hand_value(&self.dealer.hand) < 17 {
-            self.dealer
-                .draw_card(&mut self.shoe, self.number_of_decks, self.player, true);
-        }
-        // check if dealer is bust
-        if (cards::hand_value(&self.dealer.hand) > 21)
-            || (cards::hand_value(&self.dealer.hand) < cards::hand_value(&self.player.hand))
-        {
-            GameStatus::PlayerWon
-        } else if cards::hand_value(&self.dealer.hand) == cards::hand_value(&self.player.hand) {
-            GameStatus::Draw
-        } else {
-            GameStatus::DealerWon
+    // surrender is only possible as the first decision on the initial hand
+    pub fn can_surrender(&self) -> bool {
+        match (self.active(), self.player.hands.len()) {
+            (Some(hand), 1) => self.rules.surrender != SurrenderRule::None && hand.cards.len() == 2,
+            _ => false,
         }
     }
 
-    pub fn end_game(&mut self, state: GameStatus) -> () {
-        if state == GameStatus::PlayerWon {
-            self.player.balance += self.bets * 2;
+    // split aces can only be hit if the rules allow it
+    pub fn can_hit(&self) -> bool {
+        match self.active() {
+            Some(hand) => !hand.is_split_ace() || self.rules.hit_split_aces,
+            None => false,
+        }
+    }
 
-            // update stats
-            self.player.stats.update_average_win(self.bets);
-        } else if state == GameStatus::Draw {
-            self.player.balance += self.bets;
-            self.player.stats.update_average_win(0);
-        } else if state == GameStatus::DealerWon {
-            // update stats
-            self.player.stats.update_average_win(-self.bets);
+    // doubling is only possible on the first two cards of a hand
+    pub fn can_double(&self) -> bool {
+        match self.active() {
+            Some(hand) => {
+                hand.cards.len() == 2
+                    && hand.bet <= self.player.balance
+                    && (!hand.is_split_ace() || self.rules.hit_split_aces)
+                    && self.rules.double_on.allows(hand.value())
+                    && (!hand.split || self.rules.double_after_split)
+            }
+            None => false,
         }
+    }
 
-        // reset hands
-        self.dealer.hand = Vec::new();
-        self.player.hand = Vec::new();
+    pub fn can_split(&self) -> bool {
+        match self.active() {
+            Some(hand) => {
+                let splits_so_far = self.player.hands.len() - 1;
+                hand.is_pair()
+                    && hand.bet <= self.player.balance
+                    && splits_so_far < self.rules.max_splits as usize
+                    && (!hand.is_split_ace() || self.rules.resplit_aces)
+            }
+            None => false,
+        }
     }
-}
 
-#[derive(Debug, PartialEq, Serialize, Clone)]
-pub struct Game {
-    pub dealer: Dealer,
-    pub player: Player,
-    pub bets: i32,
-    pub shoe: Vec<Card>,
-    pub number_of_decks: i8,
-}
+    // every action the player can take right now
+    pub fn allowed_actions(&self) -> Vec<Action> {
+        match self.phase {
+            Phase::InsuranceOffered => {
+                // a bet of 1 is too small to be insured
+                let hand = &self.player.hands[0];
+                let insurance = hand.bet / 2;
+                if hand.is_blackjack() || (insurance > 0 && insurance <= self.player.balance) {
+                    vec![Action::TakeInsurance, Action::DeclineInsurance]
+                } else {
+                    vec![Action::DeclineInsurance]
+                }
+            }
+            Phase::PlayerTurn(_) => {
+                let checks = [
+                    (Action::Hit, self.can_hit()),
+                    (Action::Stand, true),
+                    (Action::Double, self.can_double()),
+                    (Action::Split, self.can_split()),
+                    (Action::Surrender, self.can_surrender()),
+                ];
+                checks
+                    .into_iter()
+                    .filter(|(_, allowed)| *allowed)
+                    .map(|(action, _)| action)
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
 
-impl Game {
-    pub fn new(player: Player, shoe: Vec<Card>, number_of_decks: i8) -> Self {
-        Game {
-            dealer: Dealer::new(),
-            player,
-            bets: 0,
-            shoe,
-            number_of_decks,
+    // a hand is finished once it is bust or reached 21; split aces only receive one card unless the rules allow hitting them
+    fn check_hand(&mut self, index: usize) {
+        let can_resplit = self.can_split();
+        let hand = &mut self.player.hands[index];
+        if hand.is_bust() {
+            hand.stood = true;
+            hand.outcome = Some(HandOutcome::Lost);
+        } else if hand.value() == 21
+            || (hand.is_split_ace()
+                && hand.cards.len() == 2
+                && !self.rules.hit_split_aces
+                && !can_resplit)
+        {
+            hand.stood = true;
         }
     }
 
-    pub fn start(&mut self, bets: i32) -> GameStatus {
-        // set bets
-        self.player.balance -= bets;
-        self.bets = bets;
+    // move on to the next hand which still takes cards
+    fn next_hand(&mut self, mut index: usize) -> Phase {
+        while self.player.hands[index].stood {
+            if index + 1 == self.player.hands.len() {
+                // every hand is played; the dealer only has to play if not all hands are bust
+                return if self
+                    .player
+                    .hands
+                    .iter()
+                    .all(|hand| hand.outcome == Some(HandOutcome::Lost))
+                {
+                    Phase::Settled
+                } else {
+                    Phase::DealerTurn
+                };
+            }
 
-        // update betting stats
-        self.player.stats.update_average_bet(bets);
-        self.player.stats.update_matches_played();
+            index += 1;
+            self.phase = Phase::PlayerTurn(index);
 
-        // dealer draws his first two cards
-        self.dealer
-            .inital_draw(&mut self.shoe, self.number_of_decks, &mut self.player);
+            // split hands receive their second card when it is their turn
+            if self.player.hands[index].cards.len() == 1 {
+                self.player.draw_card(&mut self.shoe, index);
+                self.check_hand(index);
+            }
+        }
+        Phase::PlayerTurn(index)
+    }
 
-        if cards::hand_value(&self.dealer.hand) == 21 {
-            GameStatus::DealerWon
+    pub fn play_dealers_turn(&mut self) -> Result<GameStatus, GameError> {
+        // the dealer only plays once all hands of the player are finished
+        if self.phase != Phase::DealerTurn {
+            return Err(GameError::WrongPhase(self.phase));
         }
-        // player draws his first card
-        else {
-            self.player.draw_card(&mut self.shoe, self.number_of_decks);
-            GameStatus::Ongoing
+
+        self.unveil_second_card();
+        while self.dealer.should_hit(&self.rules) {
+            self.dealer
+                .draw_card(&mut self.shoe, &mut self.player, true);
         }
+
+        self.compare_hands();
+        Ok(self.enter(Phase::Settled))
     }
 
-    pub fn play_action(&mut self, action: Action) -> GameStatus {
-        match action {
-            Action::Hit => {
-                self.player.draw_card(&mut self.shoe, self.number_of_decks);
-                if cards::hand_value(&self.player.hand) > 21 {
-                    GameStatus::DealerWon
-                } else if cards::hand_value(&self.player.hand) == 21 {
-                    GameStatus::PlayerWon
+    // dealer unviels his second card (or draws it, if there is no hole card)
+    fn unveil_second_card(&mut self) {
+        match self.dealer.hole_card() {
+            Some(_) => self.count_hole_card(),
+            None => {
+                self.dealer
+                    .draw_card(&mut self.shoe, &mut self.player, true);
+                self.settle_insurance();
+            }
+        }
+    }
+
+    // without a hole card, a surrender or a natural against an ace or ten leaves no hand to play against the dealer: he
+    // only draws his second card to show whether he has blackjack
+    fn check_dealer_blackjack(&mut self) -> Phase {
+        self.unveil_second_card();
+        self.compare_hands();
+        Phase::Settled
+    }
+
+    // compare every hand which is still in play with the dealers hand
+    fn compare_hands(&mut self) {
+        let dealer_value = self.dealer.evaluate();
+        let dealer_has_blackjack = dealer_value.is_blackjack;
+        if dealer_has_blackjack
+            && !self.rules.dealer_peeks
+            && self.rules.surrender == SurrenderRule::Late
+        {
+            for hand in self.player.hands.iter_mut() {
+                if hand.outcome == Some(HandOutcome::Surrendered) {
+                    hand.outcome = Some(HandOutcome::Lost);
+                }
+            }
+        }
+        for hand in self
+            .player
+            .hands
+            .iter_mut()
+            .filter(|hand| hand.outcome.is_none())
+        {
+            // a dealer blackjack (only possible without a hole card) beats every hand except a natural
+            hand.outcome = if hand.is_blackjack() {
+                if dealer_has_blackjack {
+                    Some(HandOutcome::Push)
                 } else {
-                    GameStatus::Ongoing
+                    Some(HandOutcome::Blackjack)
                 }
+            } else if dealer_has_blackjack {
+                Some(HandOutcome::Lost)
             }
-            Action::Stand => GameStatus::PlayerFinished,
-            Action::Double => {
-                // double bets
-                self.player.balance -= self.bets;
-                self.bets += self.bets;
+            // check if dealer is bust
+            else if dealer_value.is_bust || dealer_value.total < hand.value() {
+                Some(HandOutcome::Won)
+            } else if dealer_value.total == hand.value() {
+                Some(HandOutcome::Push)
+            } else {
+                Some(HandOutcome::Lost)
+            };
+        }
+    }
 
-                self.player.draw_card(&mut self.shoe, self.number_of_decks);
+    // summarize the outcome of all hands into the status of the round
+    fn round_status(&self) -> GameStatus {
+        if let [hand] = self.player.hands.as_slice() {
+            match hand.outcome {
+                Some(HandOutcome::Blackjack) => return GameStatus::PlayerBlackjack,
+                Some(HandOutcome::Surrendered) => return GameStatus::Surrendered,
+                _ => (),
+            }
+        }
 
-                // update stats
-                self.player.stats.update_average_bet(self.bets);
-                self.player.stats.update_times_doubled();
+        let net: i32 = self
+            .player
+            .hands
+            .iter()
+            .map(|hand| self.winnings(hand.outcome.unwrap_or(HandOutcome::Lost), hand.bet))
+            .sum();
+        match net.cmp(&0) {
+            Ordering::Greater => GameStatus::PlayerWon,
+            Ordering::Less => GameStatus::DealerWon,
+            Ordering::Equal => GameStatus::Draw,
+        }
+    }
 
-                // check if player won
-                if cards::hand_value(&self.player.hand) > 21 {
-                    GameStatus::DealerWon
-                } else if cards::hand_value(&self.player.hand) == 21 {
-                    GameStatus::PlayerWon
-                } else {
-                    GameStatus::PlayerFinished
-                }
+    // net winnings of a hand (negative if the bet is lost)
+    fn winnings(&self, outcome: HandOutcome, bet: i32) -> i32 {
+        match outcome {
+            HandOutcome::Won => bet,
+            HandOutcome::Blackjack => self.rules.blackjack_payout.winnings(bet),
+            HandOutcome::Push => 0,
+            HandOutcome::Surrendered => bet / 2 - bet,
+            HandOutcome::Lost => -bet,
+        }
+    }
+
+    // pay out every hand once the round is decided
+    fn settle(&mut self) {
+        // without a hole card, open insurance (or even money) is decided by the dealers second card even if the dealer
+        // does not play
+        let even_money = self.player.hands[0].even_money && self.dealer.hole_card().is_none();
+        if even_money || self.player.hands.iter().any(|hand| hand.insurance > 0) {
+            if self.dealer.hole_card().is_none() {
+                self.dealer
+                    .draw_card(&mut self.shoe, &mut self.player, true);
             }
-            Action::Split => unimplemented!(),
+            self.settle_insurance();
+        }
+
+        // every hand has an outcome at this point, hands without one were never played and are lost
+        let mut total_winnings = 0;
+        for index in 0..self.player.hands.len() {
+            let hand = &self.player.hands[index];
+            let winnings = self.winnings(hand.outcome.unwrap_or(HandOutcome::Lost), hand.bet);
+            self.player.balance += hand.bet + winnings;
+            total_winnings += winnings;
         }
+
+        // update stats
+        self.player.stats.update_average_win(total_winnings);
     }
 
-    pub fn play_dealers_turn(&mut self) -> GameStatus {
-        // dealer unviels his second card
-        self.player
-            .stats
-            .update_card_count(self.dealer.hand.last().unwrap().clone());
-        while cards::hand_value(&self.dealer.hand) < 17 {
-            self.dealer
-                .draw_card(&mut self.shoe, self.number_of_decks, &mut self.player, true);
+    // clear the table for the next round, the previous round was already settled
+    pub fn end_game(&mut self) -> Result<(), GameError> {
+        if self.phase != Phase::Settled {
+            return Err(GameError::WrongPhase(self.phase));
         }
-        // check if dealer is bust
-        if (cards::hand_value(&self.dealer.hand) > 21)
-            || (cards::hand_value(&self.dealer.hand) < cards::hand_value(&self.player.hand))
-        {
-            GameStatus::PlayerWon
-        } else if cards::hand_value(&self.dealer.hand) == cards::hand_value(&self.player.hand) {
-            GameStatus::Draw
-        } else {
-            GameStatus::DealerWon
+
+        // the cards of the round go to the discard tray
+        self.shoe.discard(std::mem::take(&mut self.dealer.hand));
+        for hand in std::mem::take(&mut self.player.hands) {
+            self.shoe.discard(hand.cards);
         }
+
+        // the cut card came out during the round: the shoe is shuffled and the count starts over before the next bet
+        if self.shoe.needs_shuffle() {
+            self.shoe.reshuffle();
+            self.player.stats.reset_card_count(&self.shoe);
+        }
+        self.peek_pending = false;
+        self.phase = Phase::Betting;
+        Ok(())
     }
+}
 
-    pub fn end_game(&mut self, state: GameStatus) -> () {
-        if state == GameStatus::PlayerWon {
-            self.player.balance += self.bets * 2;
-            // update stats
-            self.player.stats.update_average_win(self.bets);
-        } else if state == GameStatus::Draw {
-            self.player.balance += self.bets;
-            self.player.stats.update_average_win(0);
-        } else if state == GameStatus::DealerWon {
-            self.player.stats.update_average_win(-self.bets);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{basic_strategy::BasicStrategy, cards, rules::BlackjackPayout, strategy};
+
+    // a game which deals the given cards first: player, dealer up card, player, dealer hole card, then the draws
+    fn stacked_game(rules: RuleSet, stack: &str) -> Game {
+        let mut game = Game::new(Player::new(1_000), rules, Some(1));
+        let stack = cards::parse_hand(stack).unwrap();
+        game.replace_shoe(Shoe::stacked(&rules, &stack, Some(1)).unwrap())
+            .unwrap();
+        game
+    }
+
+    fn first_cards(seed: u64) -> String {
+        let mut game = Game::new(Player::new(10_000), RuleSet::default(), Some(seed));
+        let cards: Vec<Card> = (0..8).map(|_| game.shoe.draw()).collect();
+        cards::format_hand(&cards, true)
+    }
+
+    #[test]
+    fn same_seed_and_actions_replay_the_same_game() {
+        let rules = RuleSet::default();
+        let mut first = Game::new(Player::new(10_000), rules, Some(42));
+        let mut second = Game::new(Player::new(10_000), rules, Some(42));
+
+        // long enough to go through several shuffles of the shoe
+        let mut strategy = BasicStrategy::new(10, &rules);
+        for _ in 0..1_000 {
+            strategy::play_round(&mut first, &mut strategy).unwrap();
+            strategy::play_round(&mut second, &mut strategy).unwrap();
+            assert_eq!(first.player.hands, second.player.hands);
+            assert_eq!(first.dealer.hand, second.dealer.hand);
+            assert_eq!(first.player.balance, second.player.balance);
+        }
+        assert!(first.shoe.shuffles() > 1);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn other_seed_deals_other_cards() {
+        assert_ne!(first_cards(42), first_cards(43));
+    }
+
+    #[test]
+    fn seed_deals_the_same_cards_as_before() {
+        // a change of the shuffle would break every recorded seed
+        assert_eq!(first_cards(42), "TC 5H 9D KH 3D 5C JC KD");
+    }
+
+    #[test]
+    fn minimum_bet_cannot_be_insured() {
+        let mut game = stacked_game(RuleSet::default(), "9S AH 7D 5C");
+        assert_eq!(game.start(1), Ok(GameStatus::InsuranceOffered));
+        assert_eq!(game.allowed_actions(), vec![Action::DeclineInsurance]);
+        assert_eq!(
+            game.play_action(Action::TakeInsurance),
+            Err(GameError::IllegalAction(Action::TakeInsurance))
+        );
+        assert_eq!(game.player.hands[0].insurance, 0);
+        assert_eq!(game.player.balance, 999);
+
+        assert_eq!(
+            game.play_action(Action::DeclineInsurance),
+            Ok(GameStatus::Ongoing)
+        );
+    }
+
+    fn no_hole_card(surrender: SurrenderRule) -> RuleSet {
+        RuleSet {
+            dealer_peeks: false,
+            surrender,
+            ..RuleSet::default()
+        }
+    }
+
+    #[test]
+    fn late_surrender_without_hole_card_only_waits_for_a_dealer_blackjack() {
+        let rules = no_hole_card(SurrenderRule::Late);
+
+        // against a ten the dealer draws his second card, but not to 17
+        let mut game = stacked_game(rules, "9S KH 7D 5C 8D");
+        game.start(10).unwrap();
+        assert_eq!(
+            game.play_action(Action::Surrender),
+            Ok(GameStatus::Surrendered)
+        );
+        assert_eq!(game.phase, Phase::Settled);
+        assert_eq!(game.dealer.hand.len(), 2);
+        assert_eq!(game.shoe.peek(), cards::parse_hand("8D").unwrap().first());
+        assert_eq!(game.player.balance, 995);
+
+        // the surrender does not count against a dealer blackjack
+        let mut game = stacked_game(rules, "9S KH 7D AC");
+        game.start(10).unwrap();
+        assert_eq!(
+            game.play_action(Action::Surrender),
+            Ok(GameStatus::DealerWon)
+        );
+        assert_eq!(game.player.balance, 990);
+
+        // a dealer blackjack is impossible against a 2 to 9, the round is over right away
+        let mut game = stacked_game(rules, "9S 6H 7D 5C");
+        game.start(10).unwrap();
+        assert_eq!(
+            game.play_action(Action::Surrender),
+            Ok(GameStatus::Surrendered)
+        );
+        assert_eq!(game.dealer.hand.len(), 1);
+        assert_eq!(game.player.balance, 995);
+    }
+
+    #[test]
+    fn natural_without_hole_card_only_waits_for_a_dealer_blackjack() {
+        let rules = no_hole_card(SurrenderRule::None);
+
+        let mut game = stacked_game(rules, "AS KH KD 5C 8D");
+        assert_eq!(game.start(10), Ok(GameStatus::PlayerBlackjack));
+        assert_eq!(game.phase, Phase::Settled);
+        assert_eq!(game.dealer.hand.len(), 2);
+        assert_eq!(game.player.balance, 1_015);
+
+        let mut game = stacked_game(rules, "AS KH KD AC");
+        assert_eq!(game.start(10), Ok(GameStatus::Draw));
+        assert_eq!(game.player.balance, 1_000);
+    }
+
+    #[test]
+    fn naturals_are_settled_right_away() {
+        let mut game = stacked_game(RuleSet::default(), "AS 9H KD 7C");
+        assert_eq!(game.start(10), Ok(GameStatus::PlayerBlackjack));
+        assert_eq!(game.phase, Phase::Settled);
+        assert_eq!(game.player.balance, 1_015);
+
+        let six_to_five = RuleSet {
+            blackjack_payout: BlackjackPayout::SixToFive,
+            ..RuleSet::default()
+        };
+        let mut game = stacked_game(six_to_five, "AS 9H KD 7C");
+        assert_eq!(game.start(10), Ok(GameStatus::PlayerBlackjack));
+        assert_eq!(game.player.balance, 1_012);
+
+        let mut game = stacked_game(RuleSet::default(), "9S KH 7D AC");
+        assert_eq!(game.start(10), Ok(GameStatus::DealerWon));
+        assert_eq!(game.phase, Phase::Settled);
+        assert_eq!(game.player.balance, 990);
+
+        let mut game = stacked_game(RuleSet::default(), "AS KH KD AC");
+        assert_eq!(game.start(10), Ok(GameStatus::Draw));
+        assert_eq!(game.player.balance, 1_000);
+    }
+
+    #[test]
+    fn split_aces_receive_one_card_each() {
+        let mut game = stacked_game(RuleSet::default(), "AS 9H AD 7C KD 5C 8S");
+        assert_eq!(game.start(10), Ok(GameStatus::Ongoing));
+        assert_eq!(
+            game.play_action(Action::Split),
+            Ok(GameStatus::PlayerFinished)
+        );
+        assert_eq!(game.phase, Phase::DealerTurn);
+        assert_eq!(game.player.balance, 980);
+
+        // 21 on a split ace is no natural, both hands win 1:1 against the dealers 24
+        assert_eq!(game.play_dealers_turn(), Ok(GameStatus::PlayerWon));
+        let outcomes: Vec<_> = game.player.hands.iter().map(|hand| hand.outcome).collect();
+        assert_eq!(outcomes, vec![Some(HandOutcome::Won); 2]);
+        assert_eq!(game.player.balance, 1_020);
+    }
+
+    #[test]
+    fn pairs_can_be_resplit_and_doubled_after_the_split() {
+        let mut game = stacked_game(RuleSet::default(), "8S 6H 8D TC 8C 3D TD 9S 2C 7H");
+        game.start(10).unwrap();
+        game.play_action(Action::Split).unwrap();
+        game.play_action(Action::Split).unwrap();
+        assert_eq!(game.player.hands.len(), 3);
+
+        // 8 3 doubled to 21, then 8 9 and 8 2 stand against the dealers 16
+        game.play_action(Action::Double).unwrap();
+        assert_eq!(game.active_hand(), Some(1));
+        game.play_action(Action::Stand).unwrap();
+        game.play_action(Action::Stand).unwrap();
+        assert_eq!(game.player.balance, 960);
+
+        assert_eq!(game.play_dealers_turn(), Ok(GameStatus::PlayerWon));
+        assert_eq!(game.phase, Phase::Settled);
+        assert_eq!(game.player.balance, 1_040);
+    }
+
+    #[test]
+    fn splits_are_limited_by_the_rules() {
+        let one_split = RuleSet {
+            max_splits: 1,
+            ..RuleSet::default()
+        };
+        let mut game = stacked_game(one_split, "8S 6H 8D TC 8C");
+        game.start(10).unwrap();
+        game.play_action(Action::Split).unwrap();
+
+        assert!(!game.allowed_actions().contains(&Action::Split));
+        assert_eq!(
+            game.play_action(Action::Split),
+            Err(GameError::IllegalAction(Action::Split))
+        );
+        assert_eq!(game.player.hands.len(), 2);
+        assert_eq!(game.player.balance, 980);
+    }
+
+    #[test]
+    fn insurance_pays_two_to_one_against_a_dealer_blackjack() {
+        let mut game = stacked_game(RuleSet::default(), "9S AH 7D KC");
+        assert_eq!(game.start(10), Ok(GameStatus::InsuranceOffered));
+        assert_eq!(
+            game.play_action(Action::TakeInsurance),
+            Ok(GameStatus::DealerWon)
+        );
+        assert_eq!(game.phase, Phase::Settled);
+        assert_eq!(game.player.balance, 1_000);
+
+        // without a dealer blackjack the insurance is lost and the round goes on
+        let mut game = stacked_game(RuleSet::default(), "9S AH 7D 5C 4D");
+        game.start(10).unwrap();
+        assert_eq!(
+            game.play_action(Action::TakeInsurance),
+            Ok(GameStatus::Ongoing)
+        );
+        assert_eq!(game.player.balance, 985);
+        game.play_action(Action::Stand).unwrap();
+        assert_eq!(game.play_dealers_turn(), Ok(GameStatus::DealerWon));
+        assert_eq!(game.player.balance, 985);
+    }
+
+    #[test]
+    fn even_money_is_paid_right_away() {
+        let mut game = stacked_game(RuleSet::default(), "AS AH KD 5C");
+        assert_eq!(game.start(10), Ok(GameStatus::InsuranceOffered));
+        assert_eq!(
+            game.play_action(Action::TakeInsurance),
+            Ok(GameStatus::PlayerWon)
+        );
+        assert_eq!(game.phase, Phase::Settled);
+        assert_eq!(game.player.balance, 1_010);
+    }
+
+    #[test]
+    fn early_surrender_counts_against_a_dealer_blackjack() {
+        let early = RuleSet {
+            surrender: SurrenderRule::Early,
+            ..RuleSet::default()
+        };
+        let mut game = stacked_game(early, "9S KH 7D AC");
+        assert_eq!(game.start(10), Ok(GameStatus::Ongoing));
+        assert_eq!(
+            game.play_action(Action::Surrender),
+            Ok(GameStatus::Surrendered)
+        );
+        assert_eq!(game.phase, Phase::Settled);
+        assert_eq!(game.player.balance, 995);
+    }
+
+    #[test]
+    fn late_surrender_comes_after_the_dealer_checked_for_blackjack() {
+        let late = RuleSet {
+            surrender: SurrenderRule::Late,
+            ..RuleSet::default()
+        };
+        let mut game = stacked_game(late, "9S KH 7D AC");
+        assert_eq!(game.start(10), Ok(GameStatus::DealerWon));
+        assert_eq!(game.player.balance, 990);
+
+        let mut game = stacked_game(late, "9S KH 7D 5C");
+        game.start(10).unwrap();
+        assert_eq!(
+            game.play_action(Action::Surrender),
+            Ok(GameStatus::Surrendered)
+        );
+        assert_eq!(game.player.balance, 995);
+
+        // only the first decision on the initial hand
+        let mut game = stacked_game(late, "9S KH 2D 5C 3H");
+        game.start(10).unwrap();
+        game.play_action(Action::Hit).unwrap();
+        assert_eq!(
+            game.play_action(Action::Surrender),
+            Err(GameError::IllegalAction(Action::Surrender))
+        );
+    }
+
+    #[test]
+    fn requests_out_of_turn_are_rejected() {
+        let mut game = stacked_game(RuleSet::default(), "9S 6H 7D TC");
+        assert_eq!(
+            game.play_action(Action::Hit),
+            Err(GameError::WrongPhase(Phase::Betting))
+        );
+        assert_eq!(
+            game.play_dealers_turn(),
+            Err(GameError::WrongPhase(Phase::Betting))
+        );
+        assert_eq!(game.end_game(), Err(GameError::WrongPhase(Phase::Betting)));
+        assert_eq!(game.start(0), Err(GameError::InvalidBet(0)));
+        assert_eq!(
+            game.start(1_001),
+            Err(GameError::InsufficientBalance {
+                balance: 1_000,
+                required: 1_001
+            })
+        );
+
+        game.start(10).unwrap();
+        assert_eq!(
+            game.start(10),
+            Err(GameError::WrongPhase(Phase::PlayerTurn(0)))
+        );
+        assert_eq!(
+            game.play_dealers_turn(),
+            Err(GameError::WrongPhase(Phase::PlayerTurn(0)))
+        );
+        assert_eq!(
+            game.end_game(),
+            Err(GameError::WrongPhase(Phase::PlayerTurn(0)))
+        );
+        for action in [Action::Split, Action::Surrender, Action::TakeInsurance] {
+            assert_eq!(
+                game.play_action(action),
+                Err(GameError::IllegalAction(action))
+            );
         }
+        assert_eq!(game.player.balance, 990);
 
-        // reset hands
-        self.dealer.hand = Vec::new();
-        self.player.hand = Vec::new();
+        game.play_action(Action::Stand).unwrap();
+        assert_eq!(
+            game.play_action(Action::Hit),
+            Err(GameError::WrongPhase(Phase::DealerTurn))
+        );
+        game.play_dealers_turn().unwrap();
+        assert_eq!(
+            game.play_action(Action::Hit),
+            Err(GameError::WrongPhase(Phase::Settled))
+        );
+        assert_eq!(game.end_game(), Ok(()));
+        assert_eq!(game.phase, Phase::Betting);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn training_counts_mistakes_per_up_card() {
+        // standing on a hard 16 against a ten and on a soft 18 against a 9
+        let mut game = stacked_game(RuleSet::default(), "9S KH 7D 5C 8D AS 9C 7H 4D 2S 3C");
+        game.training = true;
+        game.start(10).unwrap();
+        game.play_action(Action::Stand).unwrap();
+        game.play_dealers_turn().unwrap();
+        game.end_game().unwrap();
+        game.start(10).unwrap();
+        game.play_action(Action::Stand).unwrap();
+
+        let decisions = game.player.stats.decisions();
+        assert_eq!(decisions.decisions(), 2);
+        let mut hard = [0; 10];
+        hard[8] = 1;
+        let mut soft = [0; 10];
+        soft[7] = 1;
+        assert_eq!(decisions.mistakes_by_up_card(HandCategory::Hard), &hard);
+        assert_eq!(decisions.mistakes_by_up_card(HandCategory::Soft), &soft);
+        assert_eq!(decisions.mistakes_by_up_card(HandCategory::Pair), &[0; 10]);
+
+        let json = rocket::serde::json::to_string(&game.player.stats).unwrap();
+        assert!(json.contains(r#""mistakes_by_up_card":{"hard":[0,0,0,0,0,0,0,0,1,0]"#));
+    }
+}
@@ -1,36 +1,50 @@
 use core::fmt;
-use std::slice::Iter;
-use rand::thread_rng;
-use rand::seq::SliceRandom;
 use serde::Serialize;
+use std::slice::Iter;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy, Serialize, PartialEq)]
 pub enum Color {
-    Clubs, // ♣
+    Clubs,    // ♣
     Diamonds, // ♦
-    Hearts, // ♥
-    Spades // ♠
+    Hearts,   // ♥
+    Spades,   // ♠
 }
 
 impl Color {
     pub fn iterator() -> Iter<'static, Color> {
-        static COLORS: [Color; 4] = [
-            Color::Clubs, 
-            Color::Diamonds, 
-            Color::Hearts, 
-            Color::Spades
-            ];
+        static COLORS: [Color; 4] = [Color::Clubs, Color::Diamonds, Color::Hearts, Color::Spades];
         COLORS.iter()
     }
 }
 
+// `{:#}` writes the suit as a letter instead of its symbol
 impl fmt::Display for Color {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Color::Clubs => write!(f, "♣"),
-            Color::Diamonds => write!(f, "♦"),
-            Color::Hearts => write!(f, "♥"),
-            Color::Spades => write!(f, "♠"),
+        match (self, f.alternate()) {
+            (Color::Clubs, false) => write!(f, "♣"),
+            (Color::Diamonds, false) => write!(f, "♦"),
+            (Color::Hearts, false) => write!(f, "♥"),
+            (Color::Spades, false) => write!(f, "♠"),
+            (Color::Clubs, true) => write!(f, "C"),
+            (Color::Diamonds, true) => write!(f, "D"),
+            (Color::Hearts, true) => write!(f, "H"),
+            (Color::Spades, true) => write!(f, "S"),
+        }
+    }
+}
+
+// suits are written as C, D, H and S (in any case) or as their symbol
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "C" | "♣" | "♧" => Ok(Color::Clubs),
+            "D" | "♦" | "♢" => Ok(Color::Diamonds),
+            "H" | "♥" | "♡" => Ok(Color::Hearts),
+            "S" | "♠" | "♤" => Ok(Color::Spades),
+            _ => Err(format!("unknown suit '{}', expected C, D, H or S", s)),
         }
     }
 }
@@ -49,7 +63,7 @@ pub enum Value {
     Jack,
     Queen,
     King,
-    Ace
+    Ace,
 }
 
 impl Value {
@@ -67,15 +81,17 @@ impl Value {
             Value::Jack,
             Value::Queen,
             Value::King,
-            Value::Ace
+            Value::Ace,
         ];
         VALUES.iter()
     }
 }
 
+// `{:#}` writes tens as T, so that every rank is a single character
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Value::Ten if f.alternate() => write!(f, "T"),
             Value::Two => write!(f, "2"),
             Value::Three => write!(f, "3"),
             Value::Four => write!(f, "4"),
@@ -93,6 +109,32 @@ impl fmt::Display for Value {
     }
 }
 
+// ranks are written as 2-9, T (or 10), J, Q, K and A
+impl FromStr for Value {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "2" => Ok(Value::Two),
+            "3" => Ok(Value::Three),
+            "4" => Ok(Value::Four),
+            "5" => Ok(Value::Five),
+            "6" => Ok(Value::Six),
+            "7" => Ok(Value::Seven),
+            "8" => Ok(Value::Eight),
+            "9" => Ok(Value::Nine),
+            "T" | "10" => Ok(Value::Ten),
+            "J" => Ok(Value::Jack),
+            "Q" => Ok(Value::Queen),
+            "K" => Ok(Value::King),
+            "A" => Ok(Value::Ace),
+            _ => Err(format!(
+                "unknown rank '{}', expected 2-9, T, J, Q, K or A",
+                s
+            )),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, PartialEq)]
 pub struct Card {
@@ -103,9 +145,9 @@ pub struct Card {
 
 impl Card {
     pub fn new(color: Color, value: Value) -> Self {
-        Self { 
-            color, 
-            value, 
+        Self {
+            color,
+            value,
             numeric_value: match value {
                 Value::Two => 2,
                 Value::Three => 3,
@@ -120,17 +162,67 @@ impl Card {
                 Value::Queen => 10,
                 Value::King => 10,
                 Value::Ace => 11, // remember: this value can be 11 or 1
-            }
+            },
         }
     }
 }
 
+// `{:#}` writes the card in ascii only, e.g. `TS` instead of `10♠`
 impl fmt::Display for Card {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}", self.value,self.color)
+        if f.alternate() {
+            write!(f, "{:#}{:#}", self.value, self.color)
+        } else {
+            write!(f, "{}{}", self.value, self.color)
+        }
+    }
+}
+
+// a card is its rank followed by its suit, e.g. `AS`, `Th`, `10h` or `T♥`
+impl FromStr for Card {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let suit = s
+            .chars()
+            .last()
+            .ok_or_else(|| String::from("a card needs a rank and a suit"))?;
+        let rank = &s[..s.len() - suit.len_utf8()];
+        let card = suit
+            .to_string()
+            .parse()
+            .and_then(|color| Ok(Card::new(color, rank.parse()?)));
+        card.map_err(|err| format!("invalid card '{}': {}", s, err))
+    }
+}
+
+// the cards of a hand separated by spaces or commas, e.g. `AS KH` or `Th,9c`
+pub fn parse_hand(hand: &str) -> Result<Vec<Card>, String> {
+    hand.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|card| !card.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+// a card in ascii only or with its suit symbol
+pub fn format_card(card: &Card, ascii: bool) -> String {
+    if ascii {
+        format!("{:#}", card)
+    } else {
+        card.to_string()
     }
 }
 
+// the cards separated by spaces, `parse_hand` reads both notations back
+pub fn format_hand(cards: &[Card], ascii: bool) -> String {
+    cards
+        .iter()
+        .map(|card| format_card(card, ascii))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// all cards of the decks in order, the shoe shuffles them
 pub fn generate_shoe(decks: i8) -> Vec<Card> {
     let mut shoe: Vec<Card> = Vec::new();
 
@@ -140,36 +232,117 @@ pub fn generate_shoe(decks: i8) -> Vec<Card> {
         for color in Color::iterator() {
             // iterate values
             for value in Value::iterator() {
-                let new_card = Card::new(color.clone(), value.clone());
+                let new_card = Card::new(*color, *value);
                 shoe.push(new_card);
             }
         }
     }
-    shoe.shuffle(&mut thread_rng());
     shoe
 }
 
-pub fn hand_value(hand: &Vec<Card>) -> i8 {
-    let mut hand_value = i8::from(0);
-    let mut aces: Vec<Card> = Vec::new();
+// evaluation of a hand; a hand is soft if one of its aces is counted as 11
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct HandValue {
+    pub total: i8,
+    pub is_soft: bool,
+    pub is_blackjack: bool, // only the first two cards can form a natural
+    pub is_bust: bool,
+}
 
-    for card in hand.iter() {
-        // sum up all non-ace cards
-        if card.value != Value::Ace {
-            hand_value += card.numeric_value;
+impl fmt::Display for HandValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_blackjack {
+            write!(f, "Blackjack")
+        } else if self.is_bust {
+            write!(f, "{} (bust)", self.total)
+        } else if self.is_soft {
+            write!(f, "soft {}", self.total)
         } else {
-            aces.push(card.clone());
+            write!(f, "{}", self.total)
         }
     }
+}
 
-    // add all aces (i.e. 11 or 1)
-    for ace in aces.iter() {
-        if (hand_value + ace.numeric_value) > 21 {
-            hand_value += 1;
-        } else {
-            hand_value += 11
+pub fn evaluate_hand(hand: &[Card]) -> HandValue {
+    // count all aces as 1 first
+    let hard_total: i8 = hand
+        .iter()
+        .map(|card| match card.value {
+            Value::Ace => 1,
+            _ => card.numeric_value,
+        })
+        .sum();
+
+    // at most one ace can be counted as 11 without busting
+    let has_ace = hand.iter().any(|card| card.value == Value::Ace);
+    let is_soft = has_ace && hard_total + 10 <= 21;
+    let total = if is_soft { hard_total + 10 } else { hard_total };
+
+    HandValue {
+        total,
+        is_soft,
+        is_blackjack: hand.len() == 2 && total == 21,
+        is_bust: total > 21,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_common_notations() {
+        let ten_of_hearts = Card::new(Color::Hearts, Value::Ten);
+        for notation in ["Th", "TH", "10h", "T♥", "10♥", "t♡"] {
+            assert_eq!(
+                notation.parse::<Card>(),
+                Ok(ten_of_hearts.clone()),
+                "{}",
+                notation
+            );
+        }
+        assert_eq!(
+            "AS".parse::<Card>(),
+            Ok(Card::new(Color::Spades, Value::Ace))
+        );
+        assert_eq!(
+            "qd".parse::<Card>(),
+            Ok(Card::new(Color::Diamonds, Value::Queen))
+        );
+        assert_eq!(
+            "2♣".parse::<Card>(),
+            Ok(Card::new(Color::Clubs, Value::Two))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_cards() {
+        for notation in ["1S", "A", "", "10", "♥", "AX", "11H", "AS KH"] {
+            assert!(notation.parse::<Card>().is_err(), "{}", notation);
         }
+        assert!("1".parse::<Value>().is_err());
+        assert!("X".parse::<Color>().is_err());
     }
 
-    hand_value
-}
\ No newline at end of file
+    #[test]
+    fn displays_cards_with_symbols_or_in_ascii() {
+        let card = Card::new(Color::Spades, Value::Ten);
+        assert_eq!(card.to_string(), "10♠");
+        assert_eq!(format!("{:#}", card), "TS");
+    }
+
+    #[test]
+    fn parses_hands() {
+        let hand = parse_hand("AS, Th 10h,T♥").unwrap();
+        assert_eq!(format_hand(&hand, false), "A♠ 10♥ 10♥ 10♥");
+        assert_eq!(parse_hand(""), Ok(Vec::new()));
+        assert!(parse_hand("AS 1S").is_err());
+    }
+
+    #[test]
+    fn formatted_hands_are_parsed_back() {
+        let shoe = generate_shoe(1);
+        assert_eq!(parse_hand(&format_hand(&shoe, true)), Ok(shoe.clone()));
+        assert_eq!(parse_hand(&format_hand(&shoe, false)), Ok(shoe));
+    }
+}
@@ -1,113 +1,607 @@
-use inquire::{error::InquireError, prompt_u32, Select};
+use std::time::Instant;
+
+use inquire::{error::InquireError, prompt_u32, Confirm, Select};
+use itertools::Itertools;
+use rocket::serde::json;
 
 use crate::{
-    blackjack::{Action, GameCliVersion, GameStatus},
-    cards::{self, Card},
-    player::Player,
+    basic_strategy::{BasicStrategy, StrategyChart},
+    blackjack::{Action, Game, GameStatus},
+    cards::{self, Card, Color, Value},
+    counting::CountingSystem,
+    house_edge,
+    player::{Hand, HandCategory, Player},
+    probability::{self, Composition},
+    rules::RuleSet,
+    shoe::Shoe,
+    simulation::{self, SimulationConfig},
+    strategy::{self, RoundResult, Strategy, TableView},
 };
 
-pub fn play_in_cli() -> () {
-    // define player actions
-    let player_options: Vec<&str> = vec!["Hit", "Stand", "Double", "Split", "Quit"];
+// command line options, every subcommand only accepts some of them
+struct Options {
+    rules: RuleSet,
+    rounds: u64,
+    bet: i32,
+    strategy: String,
+    training: bool,
+    ascii: bool, // cards are printed without suit symbols
+    counting: CountingSystem,
+    seed: Option<u64>,
+    stack: Vec<Card>,             // dealt first, before the rest of the shoe
+    chart: Option<StrategyChart>, // custom chart played instead of the generated basic strategy
+    format: String,
+    up_card: Option<Value>,
+    hand: Vec<Value>,
+    removed: Vec<Value>, // cards which are not in the shoe anymore
+    peeked: bool,
+}
+
+fn parse_options(args: &[String], allowed: &[&str]) -> Result<Options, String> {
+    let defaults = SimulationConfig::default();
+    let mut options = Options {
+        rules: defaults.rules,
+        rounds: defaults.rounds,
+        bet: 10,
+        strategy: String::from("basic"),
+        training: false,
+        ascii: false,
+        counting: CountingSystem::default(),
+        seed: None,
+        stack: Vec::new(),
+        chart: None,
+        format: String::from("text"),
+        up_card: None,
+        hand: Vec::new(),
+        removed: Vec::new(),
+        peeked: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !allowed.contains(&arg.as_str()) {
+            return Err(format!("unknown option '{}'", arg));
+        }
+        // flags do not take a value
+        match arg.as_str() {
+            "--training" => {
+                options.training = true;
+                continue;
+            }
+            "--peeked" => {
+                options.peeked = true;
+                continue;
+            }
+            "--ascii" => {
+                options.ascii = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} expects a value", arg))?;
+        match arg.as_str() {
+            "--rules" => options.rules = RuleSet::from_file(value)?,
+            "--rounds" => {
+                options.rounds = value
+                    .parse()
+                    .map_err(|_| format!("--rounds expects a number, got '{}'", value))?
+            }
+            "--bet" => {
+                options.bet =
+                    value.parse().ok().filter(|bet| *bet > 0).ok_or_else(|| {
+                        format!("--bet expects a positive number, got '{}'", value)
+                    })?
+            }
+            "--strategy" => options.strategy = value.clone(),
+            "--counting" => options.counting = value.parse()?,
+            "--seed" => {
+                options.seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("--seed expects a number, got '{}'", value))?,
+                )
+            }
+            "--shoe" => options.stack = cards::parse_hand(value)?,
+            "--chart" => options.chart = Some(StrategyChart::from_file(value)?),
+            "--format" => match value.as_str() {
+                "text" | "csv" | "json" => options.format = value.clone(),
+                _ => {
+                    return Err(format!(
+                        "--format expects text, csv or json, got '{}'",
+                        value
+                    ))
+                }
+            },
+            "--up-card" => options.up_card = Some(value.parse()?),
+            "--hand" => options.hand = parse_ranks(value)?,
+            "--removed" => options.removed = parse_ranks(value)?,
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(options)
+}
+
+// a comma separated list of ranks, e.g. `T,6`
+fn parse_ranks(ranks: &str) -> Result<Vec<Value>, String> {
+    ranks.split(',').map(|rank| rank.trim().parse()).collect()
+}
+
+fn parse_or_exit(args: &[String], allowed: &[&str]) -> Options {
+    parse_options(args, allowed).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    })
+}
+
+// parse the command line options and start the game
+pub fn run(args: &[String]) {
+    let options = parse_or_exit(
+        args,
+        &[
+            "--rules",
+            "--training",
+            "--counting",
+            "--seed",
+            "--shoe",
+            "--ascii",
+        ],
+    );
+    play_in_cli(&options);
+}
+
+// play many rounds without any input and print the results
+pub fn run_simulation(args: &[String]) {
+    let options = parse_or_exit(
+        args,
+        &[
+            "--rules",
+            "--rounds",
+            "--bet",
+            "--strategy",
+            "--chart",
+            "--counting",
+            "--seed",
+        ],
+    );
+    // the seed is always printed, so that any simulation can be repeated
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let config = SimulationConfig {
+        rounds: options.rounds,
+        rules: options.rules,
+        counting: options.counting,
+        seed: Some(seed),
+    };
+    let mut player: Box<dyn Strategy> = match options.chart {
+        Some(chart) if options.strategy == "basic" => Box::new(BasicStrategy {
+            bet: options.bet,
+            chart,
+        }),
+        Some(_) => {
+            eprintln!("--chart can only be played with the basic strategy");
+            std::process::exit(1);
+        }
+        None => strategy::by_name(&options.strategy, options.bet, &options.rules, Some(seed))
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }),
+    };
+
+    println!("Table rules: {}", config.rules);
+    println!("Seed: {}", seed);
+    println!(
+        "Simulating {} rounds with the {} strategy, betting {} per round...",
+        config.rounds, options.strategy, options.bet
+    );
+    let started = Instant::now();
+    match simulation::simulate(config, player.as_mut()) {
+        Ok(report) => {
+            println!("{}", report);
+            println!("finished in {:.1}s", started.elapsed().as_secs_f64());
+        }
+        Err(err) => {
+            eprintln!("the simulation failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// print the basic strategy chart generated for the table rules, or convert a chart file to another format
+pub fn print_chart(args: &[String]) {
+    let options = parse_or_exit(args, &["--rules", "--chart", "--format"]);
+    let chart = options
+        .chart
+        .unwrap_or_else(|| StrategyChart::for_rules(&options.rules));
+
+    match options.format.as_str() {
+        "csv" => print!("{}", chart.to_csv()),
+        "json" => println!("{}", chart.to_json()),
+        _ => {
+            println!("Basic strategy for: {}", options.rules);
+            println!();
+            print!("{}", chart);
+        }
+    }
+}
+
+// print the exact probabilities of the dealers final hands for every up card (or only the given one)
+pub fn print_dealer_odds(args: &[String]) {
+    let options = parse_or_exit(args, &["--rules", "--up-card", "--removed", "--peeked"]);
+    let shoe = shoe_without(&options, &options.removed);
+
+    let up_cards: Vec<Value> = match options.up_card {
+        Some(up_card) => vec![up_card],
+        None => vec![
+            Value::Two,
+            Value::Three,
+            Value::Four,
+            Value::Five,
+            Value::Six,
+            Value::Seven,
+            Value::Eight,
+            Value::Nine,
+            Value::Ten,
+            Value::Ace,
+        ],
+    };
 
+    println!("Table rules: {}", options.rules);
+    println!(
+        "Shoe: {} cards ({} aces, {} ten-valued){}",
+        shoe.total(),
+        shoe.count(Value::Ace),
+        shoe.count(Value::Ten),
+        if options.peeked {
+            ", the dealer peeked and has no natural"
+        } else {
+            ""
+        }
+    );
+    println!("up card        17        18        19        20        21      bust blackjack");
+    for up_card in up_cards {
+        // the up card itself is not in the shoe anymore
+        let mut remaining = shoe;
+        if let Err(err) = remaining.remove(up_card) {
+            eprintln!("{}", err);
+            continue;
+        }
+        let odds = probability::dealer_odds(
+            up_card,
+            &remaining,
+            options.rules.dealer_hits_soft_17,
+            options.peeked,
+        );
+        println!("{:<7}{}", up_card.to_string(), odds);
+    }
+}
+
+// print the exact EV of every action for a hand against an up card
+pub fn print_action_evs(args: &[String]) {
+    let options = parse_or_exit(
+        args,
+        &["--rules", "--hand", "--up-card", "--removed", "--peeked"],
+    );
+    let up_card = options.up_card.unwrap_or_else(|| {
+        eprintln!("--up-card is required");
+        std::process::exit(1);
+    });
+    if options.hand.len() < 2 {
+        eprintln!("--hand needs at least two cards, e.g. T,6");
+        std::process::exit(1);
+    }
+
+    let mut hand = Hand::new(1);
+    hand.cards = options
+        .hand
+        .iter()
+        .map(|value| Card::new(Color::Spades, *value))
+        .collect();
+    let dealt: Vec<Value> = options
+        .hand
+        .iter()
+        .chain(&options.removed)
+        .chain([&up_card])
+        .copied()
+        .collect();
+    let shoe = shoe_without(&options, &dealt);
+    let evs = probability::action_evs(&hand, up_card, &shoe, &options.rules, options.peeked);
+
+    println!("Table rules: {}", options.rules);
+    println!(
+        "{} ({}) against {}{}:",
+        hand.cards.iter().map(|card| card.value).join(" "),
+        hand.evaluate(),
+        up_card,
+        if options.peeked {
+            ", the dealer has no natural"
+        } else {
+            ""
+        }
+    );
+    let rows = [
+        ("stand", Some(evs.stand)),
+        ("hit", evs.hit),
+        ("double", evs.double),
+        ("split", evs.split),
+        ("surrender", evs.surrender),
+    ];
+    for (action, ev) in rows {
+        if let Some(ev) = ev {
+            println!("  {:<10} {:+.6}", action, ev);
+        }
+    }
+}
+
+// print the house edge of the table rules under basic strategy and what every rule adds to it
+pub fn print_house_edge(args: &[String]) {
+    let options = parse_or_exit(args, &["--rules", "--format"]);
+    let report = house_edge::report(&options.rules);
+
+    match options.format.as_str() {
+        "json" => println!(
+            "{}",
+            json::to_string(&report).expect("a report can always be serialized")
+        ),
+        "csv" => {
+            eprintln!("the house edge can be printed as text or json");
+            std::process::exit(1);
+        }
+        _ => println!("{}", report),
+    }
+}
+
+// the full shoe of the rules without the given cards
+fn shoe_without(options: &Options, cards: &[Value]) -> Composition {
+    let mut shoe = Composition::full(options.rules.number_of_decks);
+    for value in cards {
+        shoe.remove(*value).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+    }
+    shoe
+}
+
+fn play_in_cli(options: &Options) {
     // initialize global objects
     let mut player: Player = Player::new(10_000);
-    let mut shoe: Vec<Card> = cards::generate_shoe(8);
+    player.stats.set_counting_system(options.counting);
+    let mut game = Game::new(player, options.rules, options.seed);
+    game.training = options.training;
+    if !options.stack.is_empty() {
+        let shoe = Shoe::stacked(&options.rules, &options.stack, Some(game.shoe.seed()))
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            });
+        game.replace_shoe(shoe).expect("no round was started yet");
+    }
+    let mut human = HumanPlayer {
+        ascii: options.ascii,
+    };
 
     println!("Welcome to this game of Blackjack!");
+    println!("Table rules: {}", options.rules);
+    println!("Counting system: {}", options.counting);
+    println!("Seed: {}", game.shoe.seed());
+    if !options.stack.is_empty() {
+        println!(
+            "The shoe is stacked with: {}",
+            cards::format_hand(&options.stack, options.ascii)
+        );
+    }
+    if options.training {
+        println!("Training mode: every decision is compared with basic strategy.");
+    }
 
-    'game: loop {
-        // initialize game
-        let mut game: GameCliVersion<'_, '_> = GameCliVersion::new(&mut player, &mut shoe, 8);
-        let mut game_status: GameStatus;
-
-        println!("{} cards remain", game.shoe.len());
-        println!("Your current balance is: {}", game.player.balance);
-        let betting_amount = prompt_u32("How much do you want to bet:");
-
-        // betting phase
-        match betting_amount {
-            Ok(amount) => {
-                println!("You are betting {:?}", amount);
-                game_status = game.start(amount as i32);
-            }
-            Err(_) => {
+    loop {
+        match strategy::play_round(&mut game, &mut human) {
+            Ok(Some(result)) => print_result(&game, result, options.ascii),
+            Ok(None) => {
                 println!("Quiting the game");
                 break;
             }
+            Err(err) => println!("{}", err),
         }
+    }
 
-        // checking the dealers cards
-        if game_status == GameStatus::DealerWon {
-            println!("The dealer has 21! You lost.");
-            game.end_game(GameStatus::DealerWon);
-            continue;
+    if options.training {
+        print_mistakes_by_up_card(&game);
+    }
+}
+
+fn print_result(game: &Game, result: RoundResult, ascii: bool) {
+    println!(
+        "The dealers hand is: {} ({})",
+        cards::format_hand(&game.dealer.hand, ascii),
+        game.dealer.evaluate()
+    );
+    for (index, hand) in game.player.hands.iter().enumerate() {
+        println!(
+            "Hand {}: {} ({}), {:?}",
+            index + 1,
+            cards::format_hand(&hand.cards, ascii),
+            hand.evaluate(),
+            hand.outcome.unwrap()
+        );
+    }
+
+    let message = match result.status {
+        GameStatus::PlayerBlackjack => "Blackjack! You won.",
+        GameStatus::PlayerWon if game.player.hands[0].even_money => "You took even money.",
+        GameStatus::PlayerWon => "You won!",
+        GameStatus::DealerWon => "The dealer won!",
+        GameStatus::Draw => "It's a draw.",
+        GameStatus::Surrendered => "You surrendered and get half of your bet back.",
+        _ => "The round is over.",
+    };
+    println!("{} ({:+})", message, result.net);
+
+    if game.training {
+        print_training_feedback(game, ascii);
+    }
+}
+
+// the mistakes of the whole session per kind of hand and dealer up card
+fn print_mistakes_by_up_card(game: &Game) {
+    let decisions = game.player.stats.decisions();
+    println!("Mistakes by dealer up card:");
+    println!("       2  3  4  5  6  7  8  9 10  A");
+    for (name, category) in [
+        ("hard", HandCategory::Hard),
+        ("soft", HandCategory::Soft),
+        ("pair", HandCategory::Pair),
+    ] {
+        let mistakes = decisions.mistakes_by_up_card(category);
+        println!(
+            "{:>5} {}",
+            name,
+            mistakes
+                .iter()
+                .map(|count| format!("{:>2}", count))
+                .join(" ")
+        );
+    }
+}
+
+// the mistakes of the last round and the accuracy so far
+fn print_training_feedback(game: &Game, ascii: bool) {
+    let round = game.player.stats.matches_played();
+    for mistake in game
+        .mistakes
+        .iter()
+        .filter(|mistake| mistake.round == round)
+    {
+        println!(
+            "Mistake: {:?} with {} against {}, basic strategy plays {:?}",
+            mistake.action,
+            cards::format_hand(&mistake.cards, ascii),
+            cards::format_card(&mistake.dealer_up_card, ascii),
+            mistake.correct_action
+        );
+    }
+
+    let decisions = game.player.stats.decisions();
+    println!(
+        "Decisions: {} of {} correct ({:.1}%)",
+        decisions.correct(),
+        decisions.decisions(),
+        decisions.accuracy()
+    );
+}
+
+// the player in front of the terminal
+struct HumanPlayer {
+    ascii: bool,
+}
+
+impl Strategy for HumanPlayer {
+    fn bet(&mut self, view: &TableView) -> Option<i32> {
+        println!("{} cards remain", view.cards_remaining);
+        println!(
+            "Running count: {:+}, true count: {:+.1}",
+            view.running_count, view.true_count
+        );
+        println!("Your current balance is: {}", view.balance);
+        if view.balance <= 0 {
+            println!("You are out of money.");
+            return None;
+        }
+
+        loop {
+            match prompt_u32("How much do you want to bet:") {
+                Ok(amount) if amount == 0 || amount as i64 > view.balance as i64 => {
+                    println!("You can bet between 1 and {}", view.balance)
+                }
+                Ok(amount) => {
+                    println!("You are betting {:?}", amount);
+                    return Some(amount as i32);
+                }
+                Err(_) => return None,
+            }
         }
+    }
 
+    fn decide(&mut self, view: &TableView) -> Action {
         println!(
             "The dealer has one card open: {}",
-            game.dealer.hand.first().unwrap()
+            cards::format_card(view.dealer_up_card.unwrap(), self.ascii)
         );
-        println!("Your card is: {}", game.player.hand.first().unwrap());
 
-        // player chooses his action
-        'decision_phase: loop {
-            if game_status != GameStatus::Ongoing {
-                break 'decision_phase;
-            }
+        // insurance (or even money with a natural) is offered if the dealer shows an ace
+        let hand = match view.hand() {
+            Some(hand) => hand,
+            None => return ask_for_insurance(view, self.ascii),
+        };
 
-            let ans: Result<&str, InquireError> =
-                Select::new("Select an action", player_options.clone()).prompt();
+        if view.hands.len() > 1 {
+            println!(
+                "Playing hand {} of {}",
+                view.active_hand.unwrap() + 1,
+                view.hands.len()
+            );
+        }
+        println!(
+            "Your hand is: {} ({})",
+            cards::format_hand(&hand.cards, self.ascii),
+            hand.evaluate()
+        );
+
+        // only the actions which are allowed right now are offered
+        let mut options: Vec<String> = view
+            .allowed_actions
+            .iter()
+            .map(|action| format!("{:?}", action))
+            .collect();
+        options.push(String::from("Hint"));
+        options.push(String::from("Quit"));
 
+        loop {
+            let ans: Result<String, InquireError> =
+                Select::new("Select an action", options.clone()).prompt();
             match ans {
-                Ok(decision) => match decision {
-                    "Hit" => {
-                        game_status = game.play_action(Action::Hit);
-                        println!("You drew a card: {}", game.player.hand.last().unwrap());
-                        println!(
-                            "Your hand value is: {}",
-                            cards::hand_value(&game.player.hand)
-                        );
-                    }
-                    "Stand" => {
-                        game_status = game.play_action(Action::Stand);
-                        println!("You finished your turn.");
-                        println!(
-                            "Your hand value is: {}",
-                            cards::hand_value(&game.player.hand)
-                        );
-                        break 'decision_phase;
-                    }
-                    "Double" => {
-                        game_status = game.play_action(Action::Double);
-                        println!("You decided to double.");
-                        println!(
-                            "Your hand value is: {}",
-                            cards::hand_value(&game.player.hand)
-                        );
-                        break 'decision_phase;
-                    }
-                    "Split" => {
-                        game_status = game.play_action(Action::Split);
-                    }
-                    "Quit" => break 'game,
-                    _ => panic!("This should not happen!"),
+                Ok(decision) if decision == "Hint" => match view.hint() {
+                    Ok(hint) => println!("{}", hint),
+                    Err(err) => println!("{}", err),
                 },
+                Ok(decision) if decision == "Quit" => {
+                    println!("Quiting the game");
+                    std::process::exit(0);
+                }
+                Ok(decision) => return decision.parse().expect("only actions are offered"),
                 Err(_) => panic!("There was an error!"),
-            };
+            }
         }
+    }
+}
 
-        // dealers turn
-        if game_status == GameStatus::DealerWon {
-            println!("The dealers hand is: {:?}", &game.dealer.hand);
-            println!("The dealer won!");
-            game.end_game(game_status);
-            continue;
-        } else {
-            game_status = game.play_dealers_turn();
-            println!("The dealers hand is: {:?}", &game.dealer.hand);
-            println!("{:?}", game_status);
-            game.end_game(game_status);
-        }
+fn ask_for_insurance(view: &TableView, ascii: bool) -> Action {
+    let hand = &view.hands[0];
+    println!("Your cards are: {}", cards::format_hand(&hand.cards, ascii));
+
+    let question = if hand.is_blackjack() {
+        "The dealer shows an ace. Do you want to take even money?"
+    } else {
+        "The dealer shows an ace. Do you want to take insurance?"
+    };
+    let take_insurance = match Confirm::new(question).with_default(false).prompt() {
+        Ok(answer) => answer,
+        Err(_) => panic!("There was an error!"),
+    };
+
+    if !take_insurance {
+        Action::DeclineInsurance
+    } else if view.is_allowed(Action::TakeInsurance) {
+        Action::TakeInsurance
+    } else if hand.bet / 2 == 0 {
+        println!("Your bet is too small to be insured.");
+        Action::DeclineInsurance
+    } else {
+        println!("You can't afford the insurance.");
+        Action::DeclineInsurance
     }
 }
@@ -0,0 +1,161 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cards::{Card, Value},
+    shoe::Shoe,
+};
+
+// card counting systems, every card seen adds its tag to the running count
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum CountingSystem {
+    #[default]
+    #[serde(rename = "hi-lo")]
+    HiLo,
+    #[serde(rename = "ko")]
+    Ko, // unbalanced, a full shoe counts up to +4 per deck
+    #[serde(rename = "hi-opt-1")]
+    HiOptI,
+    #[serde(rename = "hi-opt-2")]
+    HiOptII,
+    #[serde(rename = "omega-2")]
+    OmegaII,
+    #[serde(rename = "zen")]
+    Zen,
+    #[serde(rename = "wong-halves")]
+    WongHalves,
+}
+
+impl CountingSystem {
+    // the tags of 2 to 9, ten-valued cards and aces
+    fn tags(&self) -> [f64; 10] {
+        match self {
+            CountingSystem::HiLo => [1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, -1.0, -1.0],
+            CountingSystem::Ko => [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, -1.0, -1.0],
+            CountingSystem::HiOptI => [0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, -1.0, 0.0],
+            CountingSystem::HiOptII => [1.0, 1.0, 2.0, 2.0, 1.0, 1.0, 0.0, 0.0, -2.0, 0.0],
+            CountingSystem::OmegaII => [1.0, 1.0, 2.0, 2.0, 2.0, 1.0, 0.0, -1.0, -2.0, 0.0],
+            CountingSystem::Zen => [1.0, 1.0, 2.0, 2.0, 2.0, 1.0, 0.0, 0.0, -2.0, -1.0],
+            CountingSystem::WongHalves => [0.5, 1.0, 1.0, 1.5, 1.0, 0.5, 0.0, -0.5, -1.0, -1.0],
+        }
+    }
+
+    // unbalanced systems start below zero, so that the count reaches the same pivot in every shoe
+    pub fn initial_count(&self, number_of_decks: i8) -> f64 {
+        match self {
+            CountingSystem::Ko => 4.0 - 4.0 * number_of_decks as f64,
+            _ => 0.0,
+        }
+    }
+
+    pub fn tag(&self, value: Value) -> f64 {
+        let index = match value {
+            Value::Two => 0,
+            Value::Three => 1,
+            Value::Four => 2,
+            Value::Five => 3,
+            Value::Six => 4,
+            Value::Seven => 5,
+            Value::Eight => 6,
+            Value::Nine => 7,
+            Value::Ten | Value::Jack | Value::Queen | Value::King => 8,
+            Value::Ace => 9,
+        };
+        self.tags()[index]
+    }
+}
+
+impl FromStr for CountingSystem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hi-lo" => Ok(CountingSystem::HiLo),
+            "ko" => Ok(CountingSystem::Ko),
+            "hi-opt-1" => Ok(CountingSystem::HiOptI),
+            "hi-opt-2" => Ok(CountingSystem::HiOptII),
+            "omega-2" => Ok(CountingSystem::OmegaII),
+            "zen" => Ok(CountingSystem::Zen),
+            "wong-halves" => Ok(CountingSystem::WongHalves),
+            _ => Err(format!(
+                "unknown counting system '{}', expected hi-lo, ko, hi-opt-1, hi-opt-2, omega-2, zen or wong-halves",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CountingSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CountingSystem::HiLo => "Hi-Lo",
+            CountingSystem::Ko => "KO",
+            CountingSystem::HiOptI => "Hi-Opt I",
+            CountingSystem::HiOptII => "Hi-Opt II",
+            CountingSystem::OmegaII => "Omega II",
+            CountingSystem::Zen => "Zen",
+            CountingSystem::WongHalves => "Wong Halves",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// the count of the cards the player has seen, kept with one counting system
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct CardCounter {
+    system: CountingSystem,
+    running_count: f64,
+    true_count: f64, // running count per deck left in the shoe (at least half a deck)
+    aces_seen: i32,  // ace side count, most useful with systems which do not count aces
+    ace_surplus: f64, // aces left per deck left in the shoe above the average of 4
+    #[serde(skip)]
+    shuffles: u32, // of the shoe when counting started
+}
+
+impl CardCounter {
+    pub fn new(system: CountingSystem) -> Self {
+        CardCounter {
+            system,
+            running_count: 0.0,
+            true_count: 0.0,
+            aces_seen: 0,
+            ace_surplus: 0.0,
+            shuffles: 0,
+        }
+    }
+
+    // a freshly shuffled shoe starts a new count
+    pub fn reset(&mut self, shoe: &Shoe) {
+        *self = CardCounter {
+            running_count: self.system.initial_count(shoe.number_of_decks()),
+            shuffles: shoe.shuffles(),
+            ..CardCounter::new(self.system)
+        };
+    }
+
+    pub fn running_count(&self) -> f64 {
+        self.running_count
+    }
+
+    pub fn true_count(&self) -> f64 {
+        self.true_count
+    }
+
+    // count a card which was just dealt from (or unveiled at the table of) the shoe
+    pub fn count(&mut self, card: &Card, shoe: &Shoe) {
+        if shoe.shuffles() != self.shuffles {
+            self.reset(shoe);
+        }
+
+        self.running_count += self.system.tag(card.value);
+        if card.value == Value::Ace {
+            self.aces_seen += 1;
+        }
+
+        let decks_remaining = (shoe.cards_remaining() as f64 / 52.0).max(0.5);
+        let aces_remaining = (4 * shoe.number_of_decks() as i32 - self.aces_seen).max(0);
+        self.true_count = self.running_count / decks_remaining;
+        self.ace_surplus = aces_remaining as f64 / decks_remaining - 4.0;
+    }
+}
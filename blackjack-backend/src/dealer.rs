@@ -1,10 +1,15 @@
 use serde::Serialize;
 
-use crate::{cards::{self, generate_shoe, Card}, player::Player};
+use crate::{
+    cards::{self, Card, HandValue},
+    player::Player,
+    rules::RuleSet,
+    shoe::Shoe,
+};
 
 #[derive(Debug, PartialEq, Serialize, Clone)]
 pub struct Dealer {
-    pub hand: Vec<Card>
+    pub hand: Vec<Card>,
 }
 
 impl Dealer {
@@ -12,27 +17,34 @@ impl Dealer {
         Dealer { hand: Vec::new() }
     }
 
-    pub fn draw_card(&mut self, shoe: &mut Vec<Card>, number_of_decks: i8, player_ref: &mut Player, card_is_visible: bool) -> () {
-        let mut card = shoe.pop();
-        
-        // if shoe is empty, refill with new decks
-        if card == None {
-            shoe.append(&mut generate_shoe(number_of_decks));
-            card = shoe.pop();
-        }
+    pub fn draw_card(&mut self, shoe: &mut Shoe, player_ref: &mut Player, card_is_visible: bool) {
+        let card = shoe.draw();
 
         // update card count stat
         if card_is_visible {
-            player_ref.stats.update_card_count(card.clone().unwrap());
+            player_ref.stats.update_card_count(&card, shoe);
         }
 
-        self.hand.push(card.unwrap());
+        self.hand.push(card);
+    }
+
+    pub fn evaluate(&self) -> HandValue {
+        cards::evaluate_hand(&self.hand)
     }
 
-    pub fn inital_draw(&mut self, shoe: &mut Vec<Card>, number_of_decks: i8, player_ref: &mut Player) -> i8 {
-        self.draw_card(shoe, number_of_decks, player_ref, true);
-        self.draw_card(shoe, number_of_decks, player_ref, false);
+    // the dealer draws to 17 and, depending on the rules, also hits a soft 17
+    pub fn should_hit(&self, rules: &RuleSet) -> bool {
+        let value = self.evaluate();
+        value.total < 17 || (value.total == 17 && value.is_soft && rules.dealer_hits_soft_17)
+    }
 
-        cards::hand_value(&self.hand)
+    // the face up card, i.e. the first card dealt to the dealer
+    pub fn up_card(&self) -> Option<&Card> {
+        self.hand.first()
     }
-}
\ No newline at end of file
+
+    // the face down card, only unveiled at the dealers turn (or when he has a natural)
+    pub fn hole_card(&self) -> Option<&Card> {
+        self.hand.get(1)
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{
+    basic_strategy::StrategyChart,
+    blackjack::{Action, Game, GameError, Phase},
+    cards::Value,
+    probability::{self, Composition},
+    strategy::TableView,
+};
+
+// a shoe which runs empty is shuffled during the round, so a nearly empty one is topped up for the calculation
+const LOW_SHOE: u32 = 26;
+
+#[derive(Debug, PartialEq, Serialize, Clone, Copy)]
+pub struct ActionEv {
+    pub action: Action,
+    pub ev: f64, // expected result of the round in units of the bet of the hand
+}
+
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct Hint {
+    pub action: Action,     // what basic strategy plays
+    pub evs: Vec<ActionEv>, // every legal action, the best one first
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Basic strategy: {:?}", self.action)?;
+        for action_ev in &self.evs {
+            write!(
+                f,
+                "\n  {:<16} EV {:+.3}",
+                format!("{:?}", action_ev.action),
+                action_ev.ev
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// the basic strategy action and the exact expected value of every legal action for the cards left in the shoe
+pub fn hint(game: &Game) -> Result<Hint, GameError> {
+    let up_card = match (game.phase, game.dealer.up_card()) {
+        (Phase::InsuranceOffered | Phase::PlayerTurn(_), Some(up_card)) => up_card.value,
+        _ => return Err(GameError::WrongPhase(game.phase)),
+    };
+    let action = StrategyChart::for_rules(&game.rules).recommend(&TableView::new(game));
+
+    // the player can not see the hole card, so it could be any card of the shoe
+    let mut shoe = Composition::of_shoe(&game.shoe);
+    shoe.add(&Composition::from_cards(&game.dealer.hand[1..]));
+    if shoe.total() < LOW_SHOE {
+        shoe.add(&Composition::full(game.rules.number_of_decks));
+    }
+
+    let evs: Box<dyn Fn(Action) -> Option<f64>> = match game.phase {
+        Phase::PlayerTurn(index) => {
+            let peeked = game.rules.dealer_peeks && !game.peek_pending;
+            let evs = probability::action_evs(
+                &game.player.hands[index],
+                up_card,
+                &shoe,
+                &game.rules,
+                peeked,
+            );
+            Box::new(move |action| evs.ev(action))
+        }
+        _ => Box::new(insurance_evs(game, up_card, &shoe)),
+    };
+
+    let mut evs: Vec<ActionEv> = game
+        .allowed_actions()
+        .into_iter()
+        .filter_map(|action| evs(action).map(|ev| ActionEv { action, ev }))
+        .collect();
+    evs.sort_by(|a, b| b.ev.total_cmp(&a.ev));
+
+    Ok(Hint { action, evs })
+}
+
+// declining means playing the hand the best way, insurance is a side bet of half the bet which pays 2:1
+fn insurance_evs(
+    game: &Game,
+    up_card: Value,
+    shoe: &Composition,
+) -> impl Fn(Action) -> Option<f64> {
+    let hand = &game.player.hands[0];
+    let decline = probability::action_evs(hand, up_card, shoe, &game.rules, false).best();
+    let natural_probability = shoe.count(Value::Ten) as f64 / shoe.total() as f64;
+    let take = if hand.is_blackjack() {
+        1.0 // even money
+    } else {
+        decline + 0.5 * (2.0 * natural_probability - (1.0 - natural_probability))
+    };
+
+    move |action| match action {
+        Action::TakeInsurance => Some(take),
+        Action::DeclineInsurance => Some(decline),
+        _ => None,
+    }
+}
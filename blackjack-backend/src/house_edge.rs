@@ -0,0 +1,252 @@
+use std::{collections::HashMap, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    basic_strategy::StrategyChart,
+    cards::{Card, Color, Value},
+    player::Hand,
+    probability::{Composition, EvCalculator},
+    rules::{DoubleRule, RuleSet, SurrenderRule},
+};
+
+// one rank per entry of a composition, jacks, queens and kings are counted as tens
+const RANKS: [Value; 10] = [
+    Value::Ace,
+    Value::Two,
+    Value::Three,
+    Value::Four,
+    Value::Five,
+    Value::Six,
+    Value::Seven,
+    Value::Eight,
+    Value::Nine,
+    Value::Ten,
+];
+
+// a rule of the reported rule set and how to apply it to other rules
+type RuleChange<'a> = (String, &'a dyn Fn(&mut RuleSet));
+
+// how much a rule changes the house edge, in percent of the initial bet
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct RuleEffect {
+    pub rule: String,
+    pub effect: f64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct HouseEdgeReport {
+    pub rules: RuleSet,
+    pub house_edge: f64, // in percent of the initial bet, negative if the player has the edge
+    pub reference_rules: RuleSet,
+    pub reference_edge: f64,
+    pub effects: Vec<RuleEffect>, // the rules which differ from the reference, they add up to the house edge
+}
+
+impl fmt::Display for HouseEdgeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Table rules: {}", self.rules)?;
+        writeln!(f, "House edge: {:+.3}%", self.house_edge)?;
+        writeln!(f)?;
+        writeln!(f, "{:<32} {:+.3}%", "reference rules", self.reference_edge)?;
+        for effect in &self.effects {
+            writeln!(f, "{:<32} {:+.3}%", effect.rule, effect.effect)?;
+        }
+        write!(f, "(reference: {})", self.reference_rules)
+    }
+}
+
+// the house edge of the rules and what every rule which differs from the default rules adds to it. The rules are
+// changed one after another, so every effect is measured on top of the rules before it.
+pub fn report(rules: &RuleSet) -> HouseEdgeReport {
+    // pairs are split once at most, so only whether splitting is allowed at all is part of the calculation. The number
+    // of splits, resplitting aces, insurance and the shoe are not.
+    let reference_rules = RuleSet {
+        max_splits: if rules.max_splits > 0 {
+            rules.max_splits
+        } else {
+            RuleSet::default().max_splits
+        },
+        resplit_aces: rules.resplit_aces,
+        insurance: rules.insurance,
+        penetration: rules.penetration,
+        burn_cards: rules.burn_cards,
+        ..RuleSet::default()
+    };
+    let reference_edge = house_edge(&reference_rules);
+
+    let target = *rules;
+    let changes: [RuleChange; 9] = [
+        (
+            match target.number_of_decks {
+                1 => String::from("1 deck"),
+                decks => format!("{} decks", decks),
+            },
+            &|rules| rules.number_of_decks = target.number_of_decks,
+        ),
+        (
+            String::from(if target.dealer_hits_soft_17 {
+                "dealer hits soft 17"
+            } else {
+                "dealer stands on soft 17"
+            }),
+            &|rules| rules.dealer_hits_soft_17 = target.dealer_hits_soft_17,
+        ),
+        (
+            format!("blackjack pays {}", target.blackjack_payout),
+            &|rules| rules.blackjack_payout = target.blackjack_payout,
+        ),
+        (
+            format!(
+                "double on {}",
+                match target.double_on {
+                    DoubleRule::AnyTwo => "any two cards",
+                    DoubleRule::NineToEleven => "9-11",
+                    DoubleRule::TenToEleven => "10-11",
+                }
+            ),
+            &|rules| rules.double_on = target.double_on,
+        ),
+        (
+            String::from(if target.double_after_split {
+                "double after split"
+            } else {
+                "no double after split"
+            }),
+            &|rules| rules.double_after_split = target.double_after_split,
+        ),
+        (
+            String::from(if target.max_splits > 0 {
+                "splitting pairs"
+            } else {
+                "no splitting"
+            }),
+            &|rules| rules.max_splits = target.max_splits,
+        ),
+        (
+            String::from(if target.hit_split_aces {
+                "hit split aces"
+            } else {
+                "no hitting split aces"
+            }),
+            &|rules| rules.hit_split_aces = target.hit_split_aces,
+        ),
+        (
+            String::from(match target.surrender {
+                SurrenderRule::None => "no surrender",
+                SurrenderRule::Late => "late surrender",
+                SurrenderRule::Early => "early surrender",
+            }),
+            &|rules| rules.surrender = target.surrender,
+        ),
+        (
+            String::from(if target.dealer_peeks {
+                "dealer peeks"
+            } else {
+                "no hole card"
+            }),
+            &|rules| rules.dealer_peeks = target.dealer_peeks,
+        ),
+    ];
+
+    let mut current = reference_rules;
+    let mut edge = reference_edge;
+    let mut effects = Vec::new();
+    for (rule, apply) in changes {
+        let mut next = current;
+        apply(&mut next);
+        if next == current {
+            continue;
+        }
+
+        let next_edge = house_edge(&next);
+        effects.push(RuleEffect {
+            rule,
+            effect: next_edge - edge,
+        });
+        current = next;
+        edge = next_edge;
+    }
+
+    HouseEdgeReport {
+        rules: *rules,
+        house_edge: edge,
+        reference_rules,
+        reference_edge,
+        effects,
+    }
+}
+
+// the exact house edge in percent of a round off the top of a fresh shoe: the first decision of every hand is
+// made by the basic strategy chart, later decisions (after hitting or splitting) are the best ones for the
+// cards left. Insurance is never taken and split pairs are not resplit.
+pub fn house_edge(rules: &RuleSet) -> f64 {
+    let chart = StrategyChart::for_rules(rules);
+    let shoe = Composition::full(rules.number_of_decks);
+
+    let mut calculators: Vec<EvCalculator> = RANKS
+        .iter()
+        .map(|up_card| EvCalculator::new(rules, *up_card))
+        .collect();
+    // the order of the player cards does not matter
+    let mut deals: HashMap<(usize, usize, usize), f64> = HashMap::new();
+    let mut ev = 0.0;
+    for (first, first_card) in RANKS.into_iter().enumerate() {
+        for (up_card, up_card_value) in RANKS.into_iter().enumerate() {
+            for (second, second_card) in RANKS.into_iter().enumerate() {
+                // the cards are dealt to the player, the dealer and the player again
+                let mut remaining = shoe;
+                let mut probability = 1.0;
+                for value in [first_card, up_card_value, second_card] {
+                    probability *= remaining.count(value) as f64 / remaining.total() as f64;
+                    if remaining.remove(value).is_err() {
+                        break;
+                    }
+                }
+                if probability == 0.0 {
+                    continue;
+                }
+
+                let key = (first.min(second), first.max(second), up_card);
+                let deal_ev = *deals.entry(key).or_insert_with(|| {
+                    let calculator = &mut calculators[up_card];
+                    deal_ev(
+                        calculator,
+                        &chart,
+                        rules,
+                        [first_card, second_card],
+                        up_card_value,
+                        &remaining,
+                    )
+                });
+                ev += probability * deal_ev;
+            }
+        }
+    }
+    -100.0 * ev
+}
+
+// EV of the action the chart plays with the two cards against the up card
+fn deal_ev(
+    calculator: &mut EvCalculator,
+    chart: &StrategyChart,
+    rules: &RuleSet,
+    cards: [Value; 2],
+    up_card: Value,
+    shoe: &Composition,
+) -> f64 {
+    let mut hand = Hand::new(1);
+    hand.cards = cards
+        .iter()
+        .map(|value| Card::new(Color::Spades, *value))
+        .collect();
+    let action = chart.action(
+        &hand,
+        &Card::new(Color::Hearts, up_card),
+        rules,
+        &|action| calculator.allows(&hand, action),
+    );
+    calculator
+        .action_ev(&hand, shoe, false, action)
+        .expect("the chart only plays allowed actions")
+}
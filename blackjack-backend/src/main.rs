@@ -1,19 +1,44 @@
-mod cards;
-mod player;
-mod dealer;
+mod basic_strategy;
 mod blackjack;
+mod cards;
 mod cli;
+mod counting;
+mod dealer;
+mod hint;
+mod house_edge;
+mod player;
+mod probability;
+mod rules;
 mod server;
+mod session;
+mod shoe;
+mod simulation;
+mod strategy;
+
+#[macro_use]
+extern crate rocket;
 
-// this was used for testing in the terminal
-// still here for easy switch to the cli version and debugging
-// fn main() {
-//     //blackjack::test_game();
-//     cli::play_in_cli();
-// }
+// run `backend cli [--rules <file>] [--training] [--counting <system>] [--seed <n>] [--shoe <cards>] [--ascii]` to play
+// in the terminal (handy for debugging), `backend simulate [--rules <file>] [--rounds <n>] [--bet <amount>]
+// [--strategy <name>] [--chart <file>] [--counting <system>] [--seed <n>]` to simulate many rounds,
+// `backend chart [--rules <file>] [--chart <file>] [--format text|csv|json]` to print the basic strategy of the rules,
+// `backend dealer-odds [--rules <file>] [--up-card <rank>] [--removed <ranks>] [--peeked]` to print the exact
+// probabilities of the dealers final hands,
+// `backend ev [--rules <file>] --hand <ranks> --up-card <rank> [--removed <ranks>] [--peeked]` to print the exact EV
+// of every action, `backend house-edge [--rules <file>] [--format text|json]` to print the house edge of the rules and
+// what every rule adds to it, otherwise the server is started
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-#[macro_use] extern crate rocket;
-#[launch]
-fn rocket() -> _ {
-    server::start_server()
-}
\ No newline at end of file
+    match args.first().map(String::as_str) {
+        Some("cli") => cli::run(&args[1..]),
+        Some("simulate") => cli::run_simulation(&args[1..]),
+        Some("chart") => cli::print_chart(&args[1..]),
+        Some("dealer-odds") => cli::print_dealer_odds(&args[1..]),
+        Some("ev") => cli::print_action_evs(&args[1..]),
+        Some("house-edge") => cli::print_house_edge(&args[1..]),
+        _ => {
+            let _ = rocket::execute(server::start_server().launch());
+        }
+    }
+}
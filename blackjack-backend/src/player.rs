@@ -1,86 +1,306 @@
 use rocket::serde::Serialize;
 
-use crate::cards::{generate_shoe, Card};
+use crate::{
+    cards::{self, Card, HandValue, Value},
+    counting::{CardCounter, CountingSystem},
+    shoe::Shoe,
+};
 
 #[derive(Debug, Serialize, Clone, PartialEq)]
-pub struct PlayerStats { 
-    all_bets: Vec<i32>,
-    all_wins: Vec<i32>,
+pub struct PlayerStats {
+    total_bets: i64, // running sums, so long simulations do not have to keep every single bet
+    total_wins: i64,
     matches_played: i32,
     average_bet: i32,
     times_doubled: i32,
+    times_split: i32,
+    times_surrendered: i32,
+    insurance_offered: i32,
+    insurance_taken: i32,
+    insurance_won: i32, // insurance (or even money) paid off, because the dealer had a natural
+    insurance_profit: i32,
     average_win: i32,
-    card_count: i32 // value, used in card counting to determine odds of high cards
+    counter: CardCounter, // count of the cards seen, used in card counting to determine odds of high cards
+    decisions: DecisionStats, // only counted in training mode
+}
+
+// how often the player followed basic strategy, with the mistakes split up by the kind of hand and the dealers up card
+#[derive(Debug, Serialize, Clone, PartialEq, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct DecisionStats {
+    decisions: i32,
+    correct: i32,
+    accuracy: f64, // percentage of correct decisions
+    hard_mistakes: i32,
+    soft_mistakes: i32,
+    pair_mistakes: i32,
+    mistakes_by_up_card: UpCardMistakes,
+}
+
+// mistakes per dealer up card, from 2 (index 0) to ace (index 9)
+#[derive(Debug, Serialize, Clone, PartialEq, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct UpCardMistakes {
+    hard: [i32; 10],
+    soft: [i32; 10],
+    pair: [i32; 10],
 }
 
 impl PlayerStats {
     pub fn new() -> Self {
         PlayerStats {
-            all_bets: Vec::new(),
-            all_wins: Vec::new(),
+            total_bets: 0,
+            total_wins: 0,
             matches_played: 0,
             average_bet: 0,
             times_doubled: 0,
+            times_split: 0,
+            times_surrendered: 0,
+            insurance_offered: 0,
+            insurance_taken: 0,
+            insurance_won: 0,
+            insurance_profit: 0,
             average_win: 0,
-            card_count: 0
+            counter: CardCounter::new(CountingSystem::default()),
+            decisions: DecisionStats::default(),
         }
     }
 
-    pub fn update_matches_played(&mut self) -> () {
+    pub fn update_matches_played(&mut self) {
         self.matches_played += 1;
     }
 
-    pub fn update_average_bet(&mut self, new_bet: i32) -> () {
-        let total_times_betted = self.matches_played + self.times_doubled;
-        self.all_bets.push(new_bet);
-        self.average_bet = self.all_bets.iter().sum::<i32>() / (total_times_betted + 1);
+    pub fn update_average_bet(&mut self, new_bet: i32) {
+        let total_times_betted = self.matches_played + self.times_doubled + self.times_split;
+        self.total_bets += new_bet as i64;
+        self.average_bet = (self.total_bets / (total_times_betted as i64 + 1)) as i32;
     }
 
-    pub fn update_times_doubled(&mut self) -> () {
+    pub fn update_times_doubled(&mut self) {
         self.times_doubled += 1;
     }
 
-    pub fn update_average_win(&mut self, new_win: i32) -> () {
-        self.all_wins.push(new_win);
-        self.average_win = self.all_wins.iter().sum::<i32>() / self.matches_played;
+    pub fn update_times_split(&mut self) {
+        self.times_split += 1;
+    }
+
+    pub fn update_times_surrendered(&mut self) {
+        self.times_surrendered += 1;
+    }
+
+    pub fn update_insurance_offered(&mut self) {
+        self.insurance_offered += 1;
+    }
+
+    pub fn update_insurance_taken(&mut self) {
+        self.insurance_taken += 1;
     }
 
-    pub fn update_card_count(&mut self, new_card: Card) -> () {
-        if new_card.numeric_value <= 6 {
-            self.card_count += 1;
-        } else if new_card.numeric_value >= 10 {
-            self.card_count -= 1;
+    pub fn update_insurance_result(&mut self, profit: i32) {
+        if profit > 0 {
+            self.insurance_won += 1;
         }
+        self.insurance_profit += profit;
+    }
+
+    pub fn update_average_win(&mut self, new_win: i32) {
+        self.total_wins += new_win as i64;
+        self.average_win = (self.total_wins / self.matches_played as i64) as i32;
+    }
+
+    pub fn matches_played(&self) -> i32 {
+        self.matches_played
     }
 
+    pub fn decisions(&self) -> &DecisionStats {
+        &self.decisions
+    }
+
+    pub fn update_decisions(
+        &mut self,
+        category: HandCategory,
+        dealer_up_card: &Card,
+        correct: bool,
+    ) {
+        let decisions = &mut self.decisions;
+        decisions.decisions += 1;
+        if correct {
+            decisions.correct += 1;
+        } else {
+            match category {
+                HandCategory::Hard => decisions.hard_mistakes += 1,
+                HandCategory::Soft => decisions.soft_mistakes += 1,
+                HandCategory::Pair => decisions.pair_mistakes += 1,
+            }
+            let up_card = (dealer_up_card.numeric_value - 2) as usize;
+            decisions.mistakes_by_up_card.category_mut(category)[up_card] += 1;
+        }
+        decisions.accuracy = decisions.correct as f64 * 100.0 / decisions.decisions as f64;
+    }
+
+    pub fn counter(&self) -> &CardCounter {
+        &self.counter
+    }
+
+    // switching the system starts counting from scratch
+    pub fn set_counting_system(&mut self, system: CountingSystem) {
+        self.counter = CardCounter::new(system);
+    }
+
+    pub fn update_card_count(&mut self, new_card: &Card, shoe: &Shoe) {
+        self.counter.count(new_card, shoe);
+    }
+
+    // the shoe was shuffled, counting starts over
+    pub fn reset_card_count(&mut self, shoe: &Shoe) {
+        self.counter.reset(shoe);
+    }
 }
 
+impl DecisionStats {
+    pub fn decisions(&self) -> i32 {
+        self.decisions
+    }
+
+    pub fn correct(&self) -> i32 {
+        self.correct
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        self.accuracy
+    }
+
+    // mistakes on the kind of hand per dealer up card, from 2 to ace
+    pub fn mistakes_by_up_card(&self, category: HandCategory) -> &[i32; 10] {
+        match category {
+            HandCategory::Hard => &self.mistakes_by_up_card.hard,
+            HandCategory::Soft => &self.mistakes_by_up_card.soft,
+            HandCategory::Pair => &self.mistakes_by_up_card.pair,
+        }
+    }
+}
+
+impl UpCardMistakes {
+    fn category_mut(&mut self, category: HandCategory) -> &mut [i32; 10] {
+        match category {
+            HandCategory::Hard => &mut self.hard,
+            HandCategory::Soft => &mut self.soft,
+            HandCategory::Pair => &mut self.pair,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub enum HandOutcome {
+    Won,
+    Blackjack,
+    Push,
+    Surrendered,
+    Lost,
+}
+
+// the table of a basic strategy chart a hand is looked up in
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub enum HandCategory {
+    Hard,
+    Soft,
+    Pair,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Hand {
+    pub cards: Vec<Card>,
+    pub bet: i32,
+    pub doubled: bool,
+    pub split: bool,      // hand was created by splitting a pair
+    pub stood: bool,      // hand takes no more cards (stood, doubled, bust or 21)
+    pub insurance: i32, // side bet against a dealer natural, open until the dealers second card is known
+    pub even_money: bool, // natural was paid 1:1 instead of insuring it
+    pub outcome: Option<HandOutcome>,
+}
+
+impl Hand {
+    pub fn new(bet: i32) -> Self {
+        Hand {
+            cards: Vec::new(),
+            bet,
+            doubled: false,
+            split: false,
+            stood: false,
+            insurance: 0,
+            even_money: false,
+            outcome: None,
+        }
+    }
+
+    pub fn evaluate(&self) -> HandValue {
+        cards::evaluate_hand(&self.cards)
+    }
+
+    pub fn value(&self) -> i8 {
+        self.evaluate().total
+    }
+
+    pub fn is_bust(&self) -> bool {
+        self.evaluate().is_bust
+    }
+
+    // 21 with two cards after a split is not a natural
+    pub fn is_blackjack(&self) -> bool {
+        !self.split && self.evaluate().is_blackjack
+    }
+
+    pub fn is_split_ace(&self) -> bool {
+        self.split
+            && self
+                .cards
+                .first()
+                .is_some_and(|card| card.value == Value::Ace)
+    }
+
+    // pairs are split by value, i.e. any two ten-valued cards can be split
+    pub fn is_pair(&self) -> bool {
+        self.cards.len() == 2 && self.cards[0].numeric_value == self.cards[1].numeric_value
+    }
+
+    // a pair which can not be split is played by its total
+    pub fn category(&self, can_split: bool) -> HandCategory {
+        if can_split && self.is_pair() {
+            HandCategory::Pair
+        } else if self.evaluate().is_soft {
+            HandCategory::Soft
+        } else {
+            HandCategory::Hard
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Clone, PartialEq)]
-#[serde(crate="rocket::serde")]
+#[serde(crate = "rocket::serde")]
 pub struct Player {
     pub balance: i32,
-    pub hand: Vec<Card>, // TODO: Replace with Hand struct to allow splitting hands
-    pub stats: PlayerStats
+    pub hands: Vec<Hand>,
+    pub stats: PlayerStats,
 }
 
 impl Player {
     pub fn new(balance: i32) -> Self {
-        Player{ balance, hand: Vec::new(), stats: PlayerStats::new() }
+        Player {
+            balance,
+            hands: Vec::new(),
+            stats: PlayerStats::new(),
+        }
     }
 
-    pub fn draw_card(&mut self, shoe: &mut Vec<Card>, number_of_decks: i8) -> () {
-        let mut card = shoe.pop();
-
-        // if shoe is empty, refill with new decks
-        if card == None {
-            shoe.append(&mut generate_shoe(number_of_decks));
-            card = shoe.pop();
-        }
+    pub fn draw_card(&mut self, shoe: &mut Shoe, hand_index: usize) {
+        let card = shoe.draw();
 
         // update card count stat
-        self.stats.update_card_count(card.clone().unwrap());
+        self.stats.update_card_count(&card, shoe);
 
-        self.hand.push(card.unwrap());
+        self.hands[hand_index].cards.push(card);
     }
-}
\ No newline at end of file
+}
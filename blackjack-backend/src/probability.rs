@@ -0,0 +1,567 @@
+use std::{cmp::Ordering, collections::HashMap, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    blackjack::Action,
+    cards::{Card, Value},
+    player::Hand,
+    rules::{RuleSet, SurrenderRule},
+    shoe::Shoe,
+};
+
+// the cards left in the shoe, counted by their points: aces first, then 2 to 9 and all ten-valued cards together
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Clone, Copy)]
+pub struct Composition {
+    counts: [u32; 10],
+}
+
+impl Composition {
+    // a freshly shuffled shoe
+    pub fn full(decks: i8) -> Self {
+        let mut counts = [4 * decks as u32; 10];
+        counts[9] = 16 * decks as u32; // ten, jack, queen and king
+        Composition { counts }
+    }
+
+    pub fn from_cards(cards: &[Card]) -> Self {
+        let mut composition = Composition { counts: [0; 10] };
+        for card in cards {
+            composition.counts[Self::index(card.value)] += 1;
+        }
+        composition
+    }
+
+    // the cards left in a shoe
+    pub fn of_shoe(shoe: &Shoe) -> Self {
+        let mut composition = Composition { counts: [0; 10] };
+        for value in Value::iterator() {
+            composition.counts[Self::index(*value)] += shoe.remaining(*value);
+        }
+        composition
+    }
+
+    // aces are index 0, ten-valued cards index 9
+    fn index(value: Value) -> usize {
+        match value {
+            Value::Ace => 0,
+            Value::Two => 1,
+            Value::Three => 2,
+            Value::Four => 3,
+            Value::Five => 4,
+            Value::Six => 5,
+            Value::Seven => 6,
+            Value::Eight => 7,
+            Value::Nine => 8,
+            Value::Ten | Value::Jack | Value::Queen | Value::King => 9,
+        }
+    }
+
+    pub fn count(&self, value: Value) -> u32 {
+        self.counts[Self::index(value)]
+    }
+
+    pub fn total(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    // take a card out of the shoe, e.g. one which was already dealt
+    pub fn remove(&mut self, value: Value) -> Result<(), String> {
+        let count = &mut self.counts[Self::index(value)];
+        if *count == 0 {
+            return Err(format!("there is no {} left in the shoe", value));
+        }
+        *count -= 1;
+        Ok(())
+    }
+
+    // put the cards of another composition into this one, e.g. fresh decks
+    pub fn add(&mut self, other: &Composition) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+    }
+
+    fn without(&self, index: usize) -> Self {
+        let mut composition = *self;
+        composition.counts[index] -= 1;
+        composition
+    }
+
+    // probability that the card with the index is drawn next
+    fn probability(&self, index: usize) -> f64 {
+        self.counts[index] as f64 / self.total() as f64
+    }
+}
+
+// probabilities of the final hands of the dealer
+#[derive(Debug, PartialEq, Serialize, Clone, Copy, Default)]
+pub struct DealerOdds {
+    pub totals: [f64; 5], // 17 to 21, without naturals
+    pub bust: f64,
+    pub blackjack: f64,
+}
+
+impl fmt::Display for DealerOdds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for probability in self.totals.iter().chain([&self.bust, &self.blackjack]) {
+            write!(f, "{:>9.4}%", probability * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+// exact distribution of the dealers final hand, found by going through every order in which he can draw from the shoe;
+// the up card has to be removed from the composition already. If the dealer peeked, he has no natural.
+pub fn dealer_odds(
+    up_card: Value,
+    shoe: &Composition,
+    hits_soft_17: bool,
+    peeked: bool,
+) -> DealerOdds {
+    let mut odds = DealerOdds::default();
+    let mut counts = shoe.counts;
+    let hand = Points::default().add(Composition::index(up_card));
+
+    draw(&mut counts, hand, 1.0, hits_soft_17, peeked, &mut odds);
+    odds
+}
+
+// the points of a hand, which is all that matters for its value
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+struct Points {
+    hard_total: i8, // aces counted as 1
+    has_ace: bool,
+    cards: u8,
+}
+
+impl Points {
+    fn of(cards: &[Card]) -> Self {
+        cards.iter().fold(Points::default(), |points, card| {
+            points.add(Composition::index(card.value))
+        })
+    }
+
+    fn total(&self) -> i8 {
+        if self.is_soft() {
+            self.hard_total + 10
+        } else {
+            self.hard_total
+        }
+    }
+
+    fn is_soft(&self) -> bool {
+        self.has_ace && self.hard_total + 10 <= 21
+    }
+
+    // the hand with one more card, given by its index in the composition
+    fn add(&self, index: usize) -> Self {
+        Points {
+            hard_total: self.hard_total + index as i8 + 1,
+            has_ace: self.has_ace || index == 0,
+            cards: self.cards + 1,
+        }
+    }
+}
+
+fn draw(
+    counts: &mut [u32; 10],
+    hand: Points,
+    probability: f64,
+    hits_soft_17: bool,
+    peeked: bool,
+    odds: &mut DealerOdds,
+) {
+    let total = hand.total();
+    if hand.cards == 2 && total == 21 {
+        odds.blackjack += probability;
+        return;
+    }
+    if total > 21 {
+        odds.bust += probability;
+        return;
+    }
+    if total >= 17 && !(total == 17 && hand.is_soft() && hits_soft_17) {
+        odds.totals[(total - 17) as usize] += probability;
+        return;
+    }
+
+    // a hole card which completes a natural is impossible once the dealer peeked
+    let excluded = |index: usize| peeked && hand.cards == 1 && hand.add(index).total() == 21;
+    let cards_left: u32 = (0..10)
+        .filter(|index| !excluded(*index))
+        .map(|index| counts[index])
+        .sum();
+
+    // if the shoe runs empty, the remaining probability is lost (this only happens with tiny compositions)
+    for index in 0..10 {
+        if counts[index] == 0 || excluded(index) {
+            continue;
+        }
+        let p = probability * counts[index] as f64 / cards_left as f64;
+        counts[index] -= 1;
+        draw(counts, hand.add(index), p, hits_soft_17, peeked, odds);
+        counts[index] += 1;
+    }
+}
+
+// expected result of every action in units of the bet of the hand, None if the rules do not allow the action
+#[derive(Debug, PartialEq, Serialize, Clone, Copy)]
+pub struct ActionEvs {
+    pub stand: f64,
+    pub hit: Option<f64>,
+    pub double: Option<f64>,
+    pub split: Option<f64>, // without resplitting
+    pub surrender: Option<f64>,
+}
+
+impl ActionEvs {
+    // EV of the best action
+    pub fn best(&self) -> f64 {
+        [self.hit, self.double, self.split, self.surrender]
+            .into_iter()
+            .flatten()
+            .fold(self.stand, f64::max)
+    }
+
+    // EV of an action, None if it is not allowed or not a decision on the hand
+    pub fn ev(&self, action: Action) -> Option<f64> {
+        match action {
+            Action::Stand => Some(self.stand),
+            Action::Hit => self.hit,
+            Action::Double => self.double,
+            Action::Split => self.split,
+            Action::Surrender => self.surrender,
+            Action::TakeInsurance | Action::DeclineInsurance => None,
+        }
+    }
+}
+
+// exact EVs of the actions for a hand against the up card; the shoe holds every card the player can not see
+// (including the hole card). After hitting, the player stands or hits, whichever is better for the composition.
+// If the dealer peeked, he has no natural.
+pub fn action_evs(
+    hand: &Hand,
+    up_card: Value,
+    shoe: &Composition,
+    rules: &RuleSet,
+    peeked: bool,
+) -> ActionEvs {
+    EvCalculator::new(rules, up_card).action_evs(hand, shoe, peeked)
+}
+
+// calculates the EVs of hands against one up card, hands calculated by the same calculator share the
+// probabilities of the dealer hands and the EVs of the hands after hitting
+pub struct EvCalculator<'a> {
+    rules: &'a RuleSet,
+    up_card: usize, // index in the composition
+    peeked: bool,   // of the hand which is calculated
+    dealer: HashMap<(Composition, bool), DealerOdds>,
+    best: HashMap<(Composition, Points, bool), f64>,
+}
+
+impl<'a> EvCalculator<'a> {
+    pub fn new(rules: &'a RuleSet, up_card: Value) -> Self {
+        EvCalculator {
+            rules,
+            up_card: Composition::index(up_card),
+            peeked: false,
+            dealer: HashMap::new(),
+            best: HashMap::new(),
+        }
+    }
+
+    // see action_evs
+    pub fn action_evs(&mut self, hand: &Hand, shoe: &Composition, peeked: bool) -> ActionEvs {
+        ActionEvs {
+            stand: self
+                .action_ev(hand, shoe, peeked, Action::Stand)
+                .expect("a hand can always stand"),
+            hit: self.action_ev(hand, shoe, peeked, Action::Hit),
+            double: self.action_ev(hand, shoe, peeked, Action::Double),
+            split: self.action_ev(hand, shoe, peeked, Action::Split),
+            surrender: self.action_ev(hand, shoe, peeked, Action::Surrender),
+        }
+    }
+
+    // whether the rules allow the action on the hand
+    pub fn allows(&self, hand: &Hand, action: Action) -> bool {
+        let rules = self.rules;
+        let total = hand.value();
+        let two_cards = hand.cards.len() == 2;
+        let can_hit = total < 21 && (!hand.is_split_ace() || rules.hit_split_aces);
+
+        match action {
+            Action::Stand => true,
+            Action::Hit => can_hit,
+            Action::Double => {
+                two_cards
+                    && can_hit
+                    && rules.double_on.allows(total)
+                    && (!hand.split || rules.double_after_split)
+            }
+            Action::Split => hand.is_pair() && rules.max_splits > 0,
+            Action::Surrender => two_cards && !hand.split && rules.surrender != SurrenderRule::None,
+            Action::TakeInsurance | Action::DeclineInsurance => false,
+        }
+    }
+
+    // EV of a single action, None if the rules do not allow it; cheaper than calculating every action
+    pub fn action_ev(
+        &mut self,
+        hand: &Hand,
+        shoe: &Composition,
+        peeked: bool,
+        action: Action,
+    ) -> Option<f64> {
+        if !self.allows(hand, action) {
+            return None;
+        }
+
+        let natural_probability = match self.up_card {
+            0 => shoe.probability(9),
+            9 => shoe.probability(0),
+            _ => 0.0,
+        };
+
+        // a dealer who peeks before the player acts only takes the initial bet with his natural
+        if self.rules.dealer_peeks && !peeked && natural_probability > 0.0 {
+            let after_peek = self.action_ev(hand, shoe, true, action)?;
+            return Some(match action {
+                // early surrender comes before the peek, late surrender only after it
+                Action::Surrender if self.rules.surrender == SurrenderRule::Early => after_peek,
+                // naturals push
+                Action::Stand if hand.is_blackjack() => (1.0 - natural_probability) * after_peek,
+                _ => -natural_probability + (1.0 - natural_probability) * after_peek,
+            });
+        }
+
+        self.peeked = peeked;
+        let points = Points::of(&hand.cards);
+        Some(match action {
+            Action::Stand if hand.is_blackjack() => {
+                // a natural only pushes against a dealer natural
+                let payout = self.rules.blackjack_payout.winnings(1000) as f64 / 1000.0;
+                payout * (1.0 - self.dealer_odds(shoe).blackjack)
+            }
+            Action::Stand => self.stand(points, shoe),
+            Action::Hit => self.hit(points, shoe),
+            Action::Double => self.double(points, shoe),
+            Action::Split => self.split(Composition::index(hand.cards[0].value), shoe),
+            // without a hole card, a late surrender only counts if the dealer has no natural
+            Action::Surrender
+                if !self.rules.dealer_peeks && self.rules.surrender == SurrenderRule::Late =>
+            {
+                -natural_probability - 0.5 * (1.0 - natural_probability)
+            }
+            Action::Surrender => -0.5,
+            Action::TakeInsurance | Action::DeclineInsurance => {
+                unreachable!("insurance is not a decision on the hand")
+            }
+        })
+    }
+}
+
+impl EvCalculator<'_> {
+    fn dealer_odds(&mut self, shoe: &Composition) -> DealerOdds {
+        if let Some(odds) = self.dealer.get(&(*shoe, self.peeked)) {
+            return *odds;
+        }
+
+        let mut odds = DealerOdds::default();
+        let mut counts = shoe.counts;
+        let hand = Points::default().add(self.up_card);
+        draw(
+            &mut counts,
+            hand,
+            1.0,
+            self.rules.dealer_hits_soft_17,
+            self.peeked,
+            &mut odds,
+        );
+        self.dealer.insert((*shoe, self.peeked), odds);
+        odds
+    }
+
+    // a dealer natural (only possible without a peek) beats every other hand
+    fn stand(&mut self, hand: Points, shoe: &Composition) -> f64 {
+        let total = hand.total();
+        if total > 21 {
+            return -1.0;
+        }
+
+        let odds = self.dealer_odds(shoe);
+        let mut ev = odds.bust - odds.blackjack;
+        for (dealer_total, probability) in (17..=21).zip(odds.totals) {
+            ev += match total.cmp(&dealer_total) {
+                Ordering::Greater => probability,
+                Ordering::Less => -probability,
+                Ordering::Equal => 0.0,
+            };
+        }
+        ev
+    }
+
+    // take one card and continue with the better of standing and hitting
+    fn hit(&mut self, hand: Points, shoe: &Composition) -> f64 {
+        self.each_card(shoe, |calculator, index, shoe| {
+            let next = hand.add(index);
+            if next.total() > 21 {
+                -1.0
+            } else {
+                calculator.best(next, shoe)
+            }
+        })
+    }
+
+    fn best(&mut self, hand: Points, shoe: &Composition) -> f64 {
+        if let Some(ev) = self.best.get(&(*shoe, hand, self.peeked)) {
+            return *ev;
+        }
+
+        let stand = self.stand(hand, shoe);
+        let ev = if hand.total() < 21 {
+            stand.max(self.hit(hand, shoe))
+        } else {
+            stand
+        };
+        self.best.insert((*shoe, hand, self.peeked), ev);
+        ev
+    }
+
+    // double the bet and take exactly one card
+    fn double(&mut self, hand: Points, shoe: &Composition) -> f64 {
+        2.0 * self.each_card(shoe, |calculator, index, shoe| {
+            calculator.stand(hand.add(index), shoe)
+        })
+    }
+
+    // both hands start with one card of the pair and are played the best way; the second hand is assumed
+    // to see the same shoe as the first one
+    fn split(&mut self, pair: usize, shoe: &Composition) -> f64 {
+        let rules = self.rules;
+        let single = Points::default().add(pair);
+        let aces = pair == 0;
+
+        let hand_ev = self.each_card(shoe, |calculator, index, shoe| {
+            let hand = single.add(index);
+            if aces && !rules.hit_split_aces {
+                return calculator.stand(hand, shoe);
+            }
+
+            let best = calculator.best(hand, shoe);
+            if rules.double_after_split && rules.double_on.allows(hand.total()) && hand.total() < 21
+            {
+                best.max(calculator.double(hand, shoe))
+            } else {
+                best
+            }
+        });
+        2.0 * hand_ev
+    }
+
+    // the result averaged over the next card
+    fn each_card(
+        &mut self,
+        shoe: &Composition,
+        mut result: impl FnMut(&mut Self, usize, &Composition) -> f64,
+    ) -> f64 {
+        let mut ev = 0.0;
+        for index in 0..10 {
+            if shoe.counts[index] > 0 {
+                ev += shoe.probability(index) * result(self, index, &shoe.without(index));
+            }
+        }
+        ev
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cards::{Card, Color};
+
+    // the shoe of the rules without the given cards
+    fn shoe_without(decks: i8, cards: &[Value]) -> Composition {
+        let mut shoe = Composition::full(decks);
+        for value in cards {
+            shoe.remove(*value).unwrap();
+        }
+        shoe
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {} ± {}, got {}",
+            expected,
+            tolerance,
+            actual
+        );
+    }
+
+    #[test]
+    fn dealer_odds_match_published_figures() {
+        // 8 decks, dealer stands on soft 17, before the peek
+        let bust = |up_card| dealer_odds(up_card, &shoe_without(8, &[up_card]), false, false).bust;
+        assert_close(bust(Value::Two), 0.3535, 0.0001);
+        assert_close(bust(Value::Five), 0.4179, 0.0001);
+        assert_close(bust(Value::Six), 0.4229, 0.0001);
+        assert_close(bust(Value::Seven), 0.2620, 0.0001);
+
+        let ace = dealer_odds(Value::Ace, &shoe_without(8, &[Value::Ace]), false, false);
+        assert_close(ace.blackjack, 128.0 / 415.0, 1e-9);
+
+        // hitting soft 17 makes the dealer bust more often with a 6
+        let h17 = dealer_odds(Value::Six, &shoe_without(8, &[Value::Six]), true, false);
+        assert!(h17.bust > bust(Value::Six));
+    }
+
+    #[test]
+    fn dealer_odds_add_up_to_one() {
+        for up_card in Value::iterator() {
+            for peeked in [false, true] {
+                let odds = dealer_odds(*up_card, &shoe_without(1, &[*up_card]), true, peeked);
+                let total = odds.totals.iter().sum::<f64>() + odds.bust + odds.blackjack;
+                assert_close(total, 1.0, 1e-9);
+                if peeked {
+                    assert_eq!(odds.blackjack, 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn action_evs_match_published_figures() {
+        let rules = RuleSet::default();
+        let hand = |values: [Value; 2]| {
+            let mut hand = Hand::new(1);
+            hand.cards = values
+                .iter()
+                .map(|value| Card::new(Color::Spades, *value))
+                .collect();
+            hand
+        };
+
+        // 16 against a ten after the peek: hitting is only slightly better than standing
+        let ten_six = hand([Value::Ten, Value::Six]);
+        let shoe = shoe_without(8, &[Value::Ten, Value::Six, Value::Ten]);
+        let evs = action_evs(&ten_six, Value::Ten, &shoe, &rules, true);
+        assert_close(evs.stand, -0.5408, 0.0001);
+        assert_close(evs.hit.unwrap(), -0.5360, 0.0001);
+
+        // 11 against a 6 is a strong double
+        let five_six = hand([Value::Five, Value::Six]);
+        let shoe = shoe_without(8, &[Value::Five, Value::Six, Value::Six]);
+        let evs = action_evs(&five_six, Value::Six, &shoe, &rules, true);
+        assert_close(evs.double.unwrap(), 0.6788, 0.0001);
+        assert_eq!(evs.best(), evs.double.unwrap());
+
+        // 8,8 against a ten is split
+        let eights = hand([Value::Eight, Value::Eight]);
+        let shoe = shoe_without(8, &[Value::Eight, Value::Eight, Value::Ten]);
+        let evs = action_evs(&eights, Value::Ten, &shoe, &rules, true);
+        assert_close(evs.split.unwrap(), -0.4849, 0.0001);
+        assert_eq!(evs.best(), evs.split.unwrap());
+    }
+}
@@ -0,0 +1,223 @@
+use std::{fmt, fs, str::FromStr};
+
+use rocket::serde::json;
+use serde::{Deserialize, Serialize};
+
+// the cut card has to leave enough cards to finish the round in which it comes out, the shoe is only shuffled between
+// rounds. A higher penetration (e.g. 0.75 of a single deck) moves the cut card up to this many cards.
+pub const MIN_CARDS_BEHIND_CUT_CARD: usize = 20;
+
+// payout for a natural blackjack, relative to the bet
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub enum BlackjackPayout {
+    #[serde(rename = "3:2")]
+    ThreeToTwo,
+    #[serde(rename = "6:5")]
+    SixToFive,
+    #[serde(rename = "1:1")]
+    EvenMoney,
+}
+
+impl FromStr for BlackjackPayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "3:2" => Ok(BlackjackPayout::ThreeToTwo),
+            "6:5" => Ok(BlackjackPayout::SixToFive),
+            "1:1" => Ok(BlackjackPayout::EvenMoney),
+            _ => Err(format!(
+                "unknown blackjack payout '{}', expected 3:2, 6:5 or 1:1",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BlackjackPayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlackjackPayout::ThreeToTwo => write!(f, "3:2"),
+            BlackjackPayout::SixToFive => write!(f, "6:5"),
+            BlackjackPayout::EvenMoney => write!(f, "1:1"),
+        }
+    }
+}
+
+impl BlackjackPayout {
+    // winnings paid on top of the returned bet (fractions are rounded down, like most casinos do)
+    pub fn winnings(&self, bets: i32) -> i32 {
+        match self {
+            BlackjackPayout::ThreeToTwo => bets * 3 / 2,
+            BlackjackPayout::SixToFive => bets * 6 / 5,
+            BlackjackPayout::EvenMoney => bets,
+        }
+    }
+}
+
+// hand totals the player is allowed to double on
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub enum DoubleRule {
+    AnyTwo,
+    NineToEleven,
+    TenToEleven,
+}
+
+impl DoubleRule {
+    pub fn allows(&self, hand_value: i8) -> bool {
+        match self {
+            DoubleRule::AnyTwo => true,
+            DoubleRule::NineToEleven => (9..=11).contains(&hand_value),
+            DoubleRule::TenToEleven => (10..=11).contains(&hand_value),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub enum SurrenderRule {
+    None,
+    Late,  // after the dealer checked for blackjack
+    Early, // before the dealer checked for blackjack
+}
+
+// all table rules the game is played with
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct RuleSet {
+    pub number_of_decks: i8,
+    pub dealer_hits_soft_17: bool,
+    pub blackjack_payout: BlackjackPayout,
+    pub double_on: DoubleRule,
+    pub double_after_split: bool,
+    pub max_splits: u8, // number of splits per round, i.e. the player can have max_splits + 1 hands
+    pub resplit_aces: bool,
+    pub hit_split_aces: bool,
+    pub surrender: SurrenderRule,
+    pub dealer_peeks: bool, // false: european no-hole-card game, the dealer draws his second card after the player
+    pub insurance: bool,    // insurance (and even money) is offered when the dealer shows an ace
+    pub penetration: f64, // share of the shoe dealt before the cut card comes out and the shoe is shuffled
+    pub burn_cards: u8,   // cards discarded face down after every shuffle
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet {
+            number_of_decks: 8, // commonly used number of decks for playing Blackjack
+            dealer_hits_soft_17: false,
+            blackjack_payout: BlackjackPayout::ThreeToTwo,
+            double_on: DoubleRule::AnyTwo,
+            double_after_split: true,
+            max_splits: 3,
+            resplit_aces: false,
+            hit_split_aces: false,
+            surrender: SurrenderRule::None,
+            dealer_peeks: true,
+            insurance: true,
+            penetration: 0.75,
+            burn_cards: 1,
+        }
+    }
+}
+
+impl RuleSet {
+    // load a rule set from a json file, missing fields fall back to the default rules
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("could not read rules file '{}': {}", path, err))?;
+        let rules: RuleSet = json::from_str(&content)
+            .map_err(|err| format!("invalid rules file '{}': {}", path, err))?;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    // cards which are never dealt before the shoe is shuffled
+    pub fn cards_behind_cut_card(&self) -> usize {
+        let cards_behind = (self.number_of_decks as f64 * 52.0 * (1.0 - self.penetration)).round();
+        (cards_behind as usize).max(MIN_CARDS_BEHIND_CUT_CARD)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=8).contains(&self.number_of_decks) {
+            return Err(format!(
+                "number_of_decks must be between 1 and 8, got {}",
+                self.number_of_decks
+            ));
+        }
+        if self.max_splits > 3 {
+            return Err(format!(
+                "max_splits must be at most 3, got {}",
+                self.max_splits
+            ));
+        }
+        if !(self.penetration > 0.0 && self.penetration <= 1.0) {
+            return Err(format!(
+                "penetration must be above 0 and at most 1, got {}",
+                self.penetration
+            ));
+        }
+        if self.burn_cards > 10 {
+            return Err(format!(
+                "burn_cards must be at most 10, got {}",
+                self.burn_cards
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RuleSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} decks, {}, blackjack pays {}, double on {}, {}, {} split(s){}{}, {}, {}{}, {:.0}% penetration",
+            self.number_of_decks,
+            if self.dealer_hits_soft_17 { "H17" } else { "S17" },
+            self.blackjack_payout,
+            match self.double_on {
+                DoubleRule::AnyTwo => "any two cards",
+                DoubleRule::NineToEleven => "9-11",
+                DoubleRule::TenToEleven => "10-11",
+            },
+            if self.double_after_split { "DAS" } else { "no DAS" },
+            self.max_splits,
+            if self.resplit_aces { ", RSA" } else { "" },
+            if self.hit_split_aces { ", hit split aces" } else { "" },
+            match self.surrender {
+                SurrenderRule::None => "no surrender",
+                SurrenderRule::Late => "late surrender",
+                SurrenderRule::Early => "early surrender",
+            },
+            if self.dealer_peeks { "peek" } else { "no hole card" },
+            if self.insurance { "" } else { ", no insurance" },
+            self.penetration * 100.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cut_card_leaves_enough_cards_to_finish_a_round() {
+        let eight_decks = RuleSet::default();
+        assert_eq!(eight_decks.cards_behind_cut_card(), 104);
+
+        // 0.75 of a single deck would leave 13 cards, the cut card is moved up instead of rejecting the rules
+        let single_deck = RuleSet {
+            number_of_decks: 1,
+            ..RuleSet::default()
+        };
+        assert_eq!(single_deck.validate(), Ok(()));
+        assert_eq!(
+            single_deck.cards_behind_cut_card(),
+            MIN_CARDS_BEHIND_CUT_CARD
+        );
+
+        let dealt_out = RuleSet {
+            penetration: 1.0,
+            ..eight_decks
+        };
+        assert_eq!(dealt_out.cards_behind_cut_card(), MIN_CARDS_BEHIND_CUT_CARD);
+    }
+}
@@ -1,37 +1,27 @@
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
 
 use rocket::fairing::{Fairing, Info, Kind};
-use rocket::http::Header;
-use rocket::serde::json::Json;
-use rocket::{Build, Rocket, State, Request, Response};
+use rocket::figment::Figment;
+use rocket::http::{ContentType, Header, Status};
+use rocket::response::{self, Responder};
+use rocket::serde::json::{self, Json};
+use rocket::tokio::sync::Semaphore;
+use rocket::{Build, Request, Response, Rocket, State};
 use serde::{Deserialize, Serialize};
 
-use crate::blackjack::{Game, GameStatus, Action};
+use crate::basic_strategy::StrategyChart;
+use crate::blackjack::{Action, Game, GameError, GameStatus, Mistake, Phase};
+use crate::cards;
+use crate::counting::CountingSystem;
 use crate::dealer::Dealer;
-use crate::rocket;
+use crate::hint::Hint;
+use crate::house_edge::HouseEdgeReport;
 use crate::player::Player;
-use crate::cards::{self, Card};
-
-pub struct GlobalState {
-    player: Player,
-    shoe: Vec<Card>,
-    game: Game,
-}
-
-impl GlobalState {
-    pub fn new() -> Self {
-        let number_of_decks = 8; // commonly used number of decks for playing Blackjack
-        let new_player = Player::new(10_000);
-        let new_shoe = cards::generate_shoe(number_of_decks);
-
-        GlobalState {
-            player: new_player.clone(),
-            shoe: new_shoe.clone(),
-            game: Game::new(new_player, new_shoe, number_of_decks),
-        }
-    }
-}
+use crate::rocket;
+use crate::rules::RuleSet;
+use crate::session::{SessionConfig, SessionError, Sessions, DEFAULT_SESSION};
+use crate::shoe::Shoe;
 
 // define data schema sent to the frontend
 #[derive(Serialize)]
@@ -39,28 +29,103 @@ pub struct GameData {
     dealer: Dealer,
     player: Player,
     bets: i32,
+    active_hand: Option<usize>,
     cards_remaining: i32,
     game_status: GameStatus,
+    training: bool,
 }
 
 impl GameData {
-    pub fn new(
-        dealer: Dealer,
-        player: Player,
-        bets: i32,
-        shoe: Vec<Card>,
-        game_status: GameStatus,
-    ) -> Self {
+    pub fn new(game: &Game) -> Self {
+        let mut dealer = game.dealer.clone();
+
+        // while the round is played, send only the first card of the dealer to the frontend (i.e. leave the second card face down)
+        if game.phase != Phase::Settled {
+            dealer.hand.truncate(1);
+        }
+
         GameData {
             dealer,
-            player,
-            bets,
-            cards_remaining: shoe.len() as i32,
-            game_status,
+            player: game.player.clone(),
+            bets: game.bets(),
+            active_hand: game.active_hand(),
+            cards_remaining: game.shoe.cards_remaining() as i32,
+            game_status: game.status(),
+            training: game.training,
+        }
+    }
+}
+
+// every failed request is answered with a json body, e.g. {"code": 409, "message": "it is the dealers turn"}
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    code: u16,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: Status, message: impl Into<String>) -> Self {
+        ApiError {
+            code: status.code,
+            message: message.into(),
         }
     }
 }
 
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status = Status::from_code(self.code).unwrap_or(Status::InternalServerError);
+        Response::build_from(Json(self).respond_to(request)?)
+            .status(status)
+            .ok()
+    }
+}
+
+// requests which do not fit the state of the round are a conflict, everything else is invalid input
+impl From<GameError> for ApiError {
+    fn from(err: GameError) -> Self {
+        let status = match err {
+            GameError::WrongPhase(_) => Status::Conflict,
+            GameError::IllegalAction(_)
+            | GameError::InvalidBet(_)
+            | GameError::InsufficientBalance { .. } => Status::UnprocessableEntity,
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+impl From<SessionError> for ApiError {
+    fn from(err: SessionError) -> Self {
+        let status = match err {
+            SessionError::NotFound(_) => Status::NotFound,
+            SessionError::LimitReached(_) => Status::ServiceUnavailable,
+            SessionError::Corrupted => Status::InternalServerError,
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
+// a random seed is kept secret while the session is played, it would reveal the hole card and every card to come
+#[derive(Serialize)]
+pub struct SessionData {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>, // only the seed the session was created with
+}
+
+// the seed of a deleted session, creating a session with it deals the same cards again
+#[derive(Serialize)]
+pub struct DeletedSession {
+    seed: u64,
+}
+
+// options of a new session, e.g. {"seed": 42}
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewSession {
+    seed: Option<u64>,
+}
+
 // define actions possible on the frontend
 #[derive(Deserialize)]
 #[serde(crate = "rocket::serde")]
@@ -68,6 +133,27 @@ pub struct Message<'r> {
     action: &'r str,
 }
 
+// turn the training mode on or off
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Training {
+    enabled: bool,
+}
+
+// select the card counting system of the player
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Counting {
+    system: CountingSystem,
+}
+
+// the cards a stacked shoe deals first, e.g. {"cards": "AS KH 6D TC"}
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StackedShoe {
+    cards: String,
+}
+
 // define message for betting amount
 #[derive(Deserialize)]
 #[serde(crate = "rocket::serde")]
@@ -75,10 +161,52 @@ pub struct Betting {
     amount: i32,
 }
 
-pub struct CORS;
+// house edge reports are kept for the last rules asked for
+const CACHED_REPORTS: usize = 32;
+
+// every calculation keeps a blocking thread busy for a few seconds, more requests than this are turned away
+const CONCURRENT_CALCULATIONS: usize = 2;
+
+pub struct HouseEdgeReports {
+    reports: Mutex<VecDeque<HouseEdgeReport>>, // the most recent report at the back
+    calculations: Arc<Semaphore>,
+}
+
+impl Default for HouseEdgeReports {
+    fn default() -> Self {
+        HouseEdgeReports {
+            reports: Mutex::new(VecDeque::new()),
+            calculations: Arc::new(Semaphore::new(CONCURRENT_CALCULATIONS)),
+        }
+    }
+}
+
+impl HouseEdgeReports {
+    // the report contains its rules, so it is looked up by them
+    fn cached(&self, rules: &RuleSet) -> Option<HouseEdgeReport> {
+        let reports = self.reports.lock().unwrap_or_else(PoisonError::into_inner);
+        reports
+            .iter()
+            .find(|report| report.rules == *rules)
+            .cloned()
+    }
+
+    fn insert(&self, report: HouseEdgeReport) {
+        let mut reports = self.reports.lock().unwrap_or_else(PoisonError::into_inner);
+        if reports.iter().any(|cached| cached.rules == report.rules) {
+            return;
+        }
+        if reports.len() == CACHED_REPORTS {
+            reports.pop_front();
+        }
+        reports.push_back(report);
+    }
+}
+
+pub struct Cors;
 
 #[rocket::async_trait]
-impl Fairing for CORS {
+impl Fairing for Cors {
     fn info(&self) -> Info {
         Info {
             name: "Add CORS headers to responses",
@@ -90,7 +218,7 @@ impl Fairing for CORS {
         response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
         response.set_header(Header::new(
             "Access-Control-Allow-Methods",
-            "PUT, POST, GET, PATCH, OPTIONS",
+            "PUT, POST, GET, PATCH, DELETE, OPTIONS",
         ));
         response.set_header(Header::new("Access-Control-Max-Age", "86400"));
         response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
@@ -99,13 +227,131 @@ impl Fairing for CORS {
 }
 
 pub fn start_server() -> Rocket<Build> {
-    rocket::build()
-        .attach(CORS)
+    let rocket = rocket::build();
+
+    // table rules can be set in the `rules` table of Rocket.toml or with ROCKET_RULES, the same goes for `sessions`
+    let rules = load_rules(rocket.figment()).unwrap_or_else(|err| panic!("{}", err));
+    let session_config =
+        load_session_config(rocket.figment()).unwrap_or_else(|err| panic!("{}", err));
+    let debug_routes = load_debug_routes(rocket.figment()).unwrap_or_else(|err| panic!("{}", err));
+
+    let rocket = rocket
+        .attach(Cors)
+        .mount(
+            "/",
+            routes![
+                index,
+                start_game,
+                init,
+                action,
+                simulate_dealer,
+                end,
+                rules,
+                hint,
+                training,
+                mistakes,
+                chart,
+                counting,
+                house_edge,
+                rules_house_edge
+            ],
+        )
         .mount(
+            "/sessions",
+            routes![
+                create_session,
+                delete_session,
+                session_init,
+                session_start_game,
+                session_action,
+                session_simulate_dealer,
+                session_end,
+                session_rules,
+                session_hint,
+                session_training,
+                session_mistakes,
+                session_chart,
+                session_counting,
+                session_house_edge
+            ],
+        )
+        .register(
             "/",
-            routes![index, start_game, init, action, simulate_dealer, end],
+            catchers![
+                not_found,
+                unprocessable_entity,
+                internal_error,
+                default_catcher
+            ],
         )
-        .manage(Arc::new(Mutex::new(GlobalState::new())))
+        .manage(Sessions::new(session_config, rules))
+        .manage(HouseEdgeReports::default());
+
+    // the debug routes let the player pick his cards, so they are only there in debug builds or if they are turned on
+    if debug_routes {
+        rocket
+            .mount("/", routes![debug_shoe])
+            .mount("/sessions", routes![session_debug_shoe])
+    } else {
+        rocket
+    }
+}
+
+fn load_rules(figment: &Figment) -> Result<RuleSet, String> {
+    if !figment.contains("rules") {
+        return Ok(RuleSet::default());
+    }
+
+    let rules: RuleSet = figment
+        .extract_inner("rules")
+        .map_err(|err| format!("invalid table rules: {}", err))?;
+    rules.validate()?;
+    Ok(rules)
+}
+
+// `debug_routes = true` in Rocket.toml or ROCKET_DEBUG_ROUTES=true
+fn load_debug_routes(figment: &Figment) -> Result<bool, String> {
+    if !figment.contains("debug_routes") {
+        return Ok(cfg!(debug_assertions));
+    }
+
+    figment
+        .extract_inner("debug_routes")
+        .map_err(|err| format!("invalid debug_routes: {}", err))
+}
+
+fn load_session_config(figment: &Figment) -> Result<SessionConfig, String> {
+    if !figment.contains("sessions") {
+        return Ok(SessionConfig::default());
+    }
+
+    figment
+        .extract_inner("sessions")
+        .map_err(|err| format!("invalid session config: {}", err))
+}
+
+#[catch(404)]
+pub fn not_found(request: &Request) -> ApiError {
+    ApiError::new(
+        Status::NotFound,
+        format!("no route for {} {}", request.method(), request.uri()),
+    )
+}
+
+// rocket uses this status if a json body does not match the expected schema
+#[catch(422)]
+pub fn unprocessable_entity() -> ApiError {
+    ApiError::new(Status::UnprocessableEntity, "the request body is invalid")
+}
+
+#[catch(500)]
+pub fn internal_error() -> ApiError {
+    ApiError::new(Status::InternalServerError, "internal server error")
+}
+
+#[catch(default)]
+pub fn default_catcher(status: Status, _request: &Request) -> ApiError {
+    ApiError::new(status, status.reason().unwrap_or("unknown error"))
 }
 
 #[get("/")]
@@ -113,126 +359,328 @@ pub fn index() -> &'static str {
     "Hello, world!"
 }
 
+// the routes without a session id play in the default session
+
 #[get("/init")]
-pub fn init(state: &State<Arc<Mutex<GlobalState>>>) -> Json<GameData> {
-    // send inital data to the frontend when first started
-    let game_data = state.lock().unwrap().game.clone();
-    Json(GameData::new(
-        game_data.dealer,
-        game_data.player,
-        game_data.bets,
-        game_data.shoe,
-        GameStatus::Initalized,
-    ))
+pub fn init(sessions: &State<Sessions>) -> Result<Json<GameData>, ApiError> {
+    session_init(sessions, DEFAULT_SESSION)
 }
 
 #[post("/startGame", data = "<betting>")]
 pub fn start_game(
-    state: &State<Arc<Mutex<GlobalState>>>,
+    sessions: &State<Sessions>,
     betting: Json<Betting>,
-) -> Json<GameData> {
-    // save the results from previous game
-    let player_data = state.lock().unwrap().game.player.clone();
-    let shoe_data = state.lock().unwrap().game.shoe.clone();
-
-    state.lock().unwrap().player = player_data.clone();
-    state.lock().unwrap().shoe = shoe_data.clone();
-
-    // reset game
-    let mut new_game = Game::new(player_data.clone(), shoe_data.clone(), state.lock().unwrap().game.number_of_decks);
-
-    // start game
-    let new_game_status = new_game.start(betting.amount);
-    state.lock().unwrap().game = new_game.clone();
-
-    // send data to frontend
-    // send only the first card of the dealer to the frontend (i.e. leave the second card face down)
-    if new_game_status != GameStatus::DealerWon {
-        let first_dealer_card = state.lock().unwrap().game.dealer.hand.first().unwrap().clone();
-        let mut modified_dealer = new_game.dealer.clone();
-        modified_dealer.hand = vec![first_dealer_card];
-        Json(GameData::new(
-            modified_dealer,
-            new_game.player.clone(),
-            new_game.bets.clone(),
-            new_game.shoe.clone(),
-            new_game_status,
-        ))
-    } else {
+) -> Result<Json<GameData>, ApiError> {
+    session_start_game(sessions, DEFAULT_SESSION, betting)
+}
 
-        Json(GameData::new(
-            new_game.dealer.clone(),
-            new_game.player.clone(),
-            new_game.bets.clone(),
-            new_game.shoe.clone(),
-            new_game_status,
-        ))
-    }
+#[get("/rules")]
+pub fn rules(sessions: &State<Sessions>) -> Result<Json<RuleSet>, ApiError> {
+    session_rules(sessions, DEFAULT_SESSION)
 }
 
 #[post("/action", data = "<message>")]
 pub fn action(
-    state: &State<Arc<Mutex<GlobalState>>>,
+    sessions: &State<Sessions>,
     message: Json<Message<'_>>,
-) -> Json<GameData> {
-    let new_game_status: GameStatus; // default value
+) -> Result<Json<GameData>, ApiError> {
+    session_action(sessions, DEFAULT_SESSION, message)
+}
+
+#[get("/simulateDealer")]
+pub fn simulate_dealer(sessions: &State<Sessions>) -> Result<Json<GameData>, ApiError> {
+    session_simulate_dealer(sessions, DEFAULT_SESSION)
+}
+
+#[post("/end", data = "<claim>")]
+pub fn end(
+    sessions: &State<Sessions>,
+    claim: Option<Json<Message<'_>>>,
+) -> Result<Json<GameData>, ApiError> {
+    session_end(sessions, DEFAULT_SESSION, claim)
+}
+
+#[get("/hint")]
+pub fn hint(sessions: &State<Sessions>) -> Result<Json<Hint>, ApiError> {
+    session_hint(sessions, DEFAULT_SESSION)
+}
+
+#[post("/training", data = "<training>")]
+pub fn training(
+    sessions: &State<Sessions>,
+    training: Json<Training>,
+) -> Result<Json<GameData>, ApiError> {
+    session_training(sessions, DEFAULT_SESSION, training)
+}
+
+#[get("/mistakes")]
+pub fn mistakes(sessions: &State<Sessions>) -> Result<Json<Vec<Mistake>>, ApiError> {
+    session_mistakes(sessions, DEFAULT_SESSION)
+}
+
+#[get("/chart?<format>")]
+pub fn chart(
+    sessions: &State<Sessions>,
+    format: Option<&str>,
+) -> Result<(ContentType, String), ApiError> {
+    session_chart(sessions, DEFAULT_SESSION, format)
+}
+
+#[post("/counting", data = "<counting>")]
+pub fn counting(
+    sessions: &State<Sessions>,
+    counting: Json<Counting>,
+) -> Result<Json<GameData>, ApiError> {
+    session_counting(sessions, DEFAULT_SESSION, counting)
+}
+
+#[get("/house-edge")]
+pub async fn house_edge(
+    sessions: &State<Sessions>,
+    reports: &State<HouseEdgeReports>,
+) -> Result<Json<HouseEdgeReport>, ApiError> {
+    session_house_edge(sessions, reports, DEFAULT_SESSION).await
+}
+
+// the house edge of any rule set, missing rules fall back to the default rules
+#[post("/house-edge", data = "<rules>")]
+pub async fn rules_house_edge(
+    reports: &State<HouseEdgeReports>,
+    rules: Json<RuleSet>,
+) -> Result<Json<HouseEdgeReport>, ApiError> {
+    rules
+        .validate()
+        .map_err(|err| ApiError::new(Status::UnprocessableEntity, err))?;
+    house_edge_report(reports, rules.into_inner()).await
+}
+
+// the calculation takes a few seconds, so it runs on its own thread instead of blocking other requests
+async fn house_edge_report(
+    reports: &HouseEdgeReports,
+    rules: RuleSet,
+) -> Result<Json<HouseEdgeReport>, ApiError> {
+    if let Some(report) = reports.cached(&rules) {
+        return Ok(Json(report));
+    }
+
+    // the permit goes with the calculation, it is not given back before the calculation is done even if the client
+    // stops waiting for it
+    let permit = Arc::clone(&reports.calculations)
+        .try_acquire_owned()
+        .map_err(|_| {
+            ApiError::new(
+                Status::ServiceUnavailable,
+                "the house edge of other rules is being calculated, try again in a few seconds",
+            )
+        })?;
+    let report = rocket::tokio::task::spawn_blocking(move || {
+        let report = crate::house_edge::report(&rules);
+        drop(permit);
+        report
+    })
+    .await
+    .map_err(|_| {
+        ApiError::new(
+            Status::InternalServerError,
+            "the house edge could not be calculated",
+        )
+    })?;
+
+    reports.insert(report.clone());
+    Ok(Json(report))
+}
+
+#[post("/debug/shoe", data = "<shoe>")]
+pub fn debug_shoe(
+    sessions: &State<Sessions>,
+    shoe: Json<StackedShoe>,
+) -> Result<Json<GameData>, ApiError> {
+    session_debug_shoe(sessions, DEFAULT_SESSION, shoe)
+}
+
+// the body is optional, but a body which is given has to be valid
+#[post("/", data = "<options>")]
+pub fn create_session(
+    sessions: &State<Sessions>,
+    options: Result<Json<NewSession>, json::Error<'_>>,
+) -> Result<Json<SessionData>, ApiError> {
+    let seed = match options {
+        Ok(options) => options.seed,
+        Err(json::Error::Parse(body, _)) if body.trim().is_empty() => None,
+        Err(err) => return Err(ApiError::new(Status::UnprocessableEntity, err.to_string())),
+    };
+    let id = sessions.create(seed)?;
+    Ok(Json(SessionData { id, seed }))
+}
+
+#[delete("/<id>")]
+pub fn delete_session(
+    sessions: &State<Sessions>,
+    id: &str,
+) -> Result<Json<DeletedSession>, ApiError> {
+    let seed = sessions.remove(id)?;
+    Ok(Json(DeletedSession { seed }))
+}
+
+#[get("/<id>/init")]
+pub fn session_init(sessions: &State<Sessions>, id: &str) -> Result<Json<GameData>, ApiError> {
+    // send inital data to the frontend when first started
+    sessions.with_game(id, |game| Ok(Json(GameData::new(game))))
+}
+
+#[post("/<id>/startGame", data = "<betting>")]
+pub fn session_start_game(
+    sessions: &State<Sessions>,
+    id: &str,
+    betting: Json<Betting>,
+) -> Result<Json<GameData>, ApiError> {
+    sessions.with_game(id, |game| {
+        game.start(betting.amount)?;
+
+        // send data to frontend
+        // if the round was already decided by a natural, the whole hand of the dealer is unveiled
+        Ok(Json(GameData::new(game)))
+    })
+}
+
+#[get("/<id>/rules")]
+pub fn session_rules(sessions: &State<Sessions>, id: &str) -> Result<Json<RuleSet>, ApiError> {
+    sessions.with_game(id, |game| Ok(Json(game.rules)))
+}
 
-    match message.action {
-        "Hit" => new_game_status = state.lock().unwrap().game.play_action(Action::Hit),
-        "Stand" => new_game_status = state.lock().unwrap().game.play_action(Action::Stand),
-        "Double" => new_game_status = state.lock().unwrap().game.play_action(Action::Double),
-        "Split" => unimplemented!(),
-        _ => panic!("unknown command."),
+// the basic strategy action and the EV of every legal action, without revealing the hole card
+#[get("/<id>/hint")]
+pub fn session_hint(sessions: &State<Sessions>, id: &str) -> Result<Json<Hint>, ApiError> {
+    sessions.with_game(id, |game| Ok(Json(crate::hint::hint(game)?)))
+}
+
+// in training mode every decision is compared with basic strategy, the accuracy is part of the player stats
+#[post("/<id>/training", data = "<training>")]
+pub fn session_training(
+    sessions: &State<Sessions>,
+    id: &str,
+    training: Json<Training>,
+) -> Result<Json<GameData>, ApiError> {
+    sessions.with_game(id, |game| {
+        game.training = training.enabled;
+        Ok(Json(GameData::new(game)))
+    })
+}
+
+#[get("/<id>/mistakes")]
+pub fn session_mistakes(
+    sessions: &State<Sessions>,
+    id: &str,
+) -> Result<Json<Vec<Mistake>>, ApiError> {
+    sessions.with_game(id, |game| Ok(Json(game.mistakes.clone())))
+}
+
+// the basic strategy chart of the table rules, as JSON (default) or CSV
+#[get("/<id>/chart?<format>")]
+pub fn session_chart(
+    sessions: &State<Sessions>,
+    id: &str,
+    format: Option<&str>,
+) -> Result<(ContentType, String), ApiError> {
+    let chart = sessions.with_game(id, |game| {
+        Ok::<_, ApiError>(StrategyChart::for_rules(&game.rules))
+    })?;
+    match format.unwrap_or("json") {
+        "json" => Ok((ContentType::JSON, chart.to_json())),
+        "csv" => Ok((ContentType::CSV, chart.to_csv())),
+        format => Err(ApiError::new(
+            Status::UnprocessableEntity,
+            format!("unknown chart format '{}', expected json or csv", format),
+        )),
     }
+}
 
-    let new_game_data = state.lock().unwrap().game.clone();
+// the player counts with the selected system from now on, the count starts over
+#[post("/<id>/counting", data = "<counting>")]
+pub fn session_counting(
+    sessions: &State<Sessions>,
+    id: &str,
+    counting: Json<Counting>,
+) -> Result<Json<GameData>, ApiError> {
+    sessions.with_game(id, |game| {
+        game.player.stats.set_counting_system(counting.system);
+        Ok(Json(GameData::new(game)))
+    })
+}
 
-    // only the first card of the dealer is sent, since the player is not finished with his turn and the dealers second card is face down
-    let first_dealer_card = state.lock().unwrap().game.dealer.hand.first().unwrap().clone();
-        let mut modified_dealer = new_game_data.dealer.clone();
-        modified_dealer.hand = vec![first_dealer_card];
-        Json(GameData::new(
-            modified_dealer,
-            new_game_data.player.clone(),
-            new_game_data.bets.clone(),
-            new_game_data.shoe.clone(),
-            new_game_status,
-        ))
+// the house edge of the table rules under basic strategy and what every rule adds to it
+#[get("/<id>/house-edge")]
+pub async fn session_house_edge(
+    sessions: &State<Sessions>,
+    reports: &State<HouseEdgeReports>,
+    id: &str,
+) -> Result<Json<HouseEdgeReport>, ApiError> {
+    let rules = sessions.with_game(id, |game| Ok::<_, ApiError>(game.rules))?;
+    house_edge_report(reports, rules).await
 }
 
-#[get("/simulateDealer")]
-pub fn simulate_dealer(state: &State<Arc<Mutex<GlobalState>>>) -> Json<GameData> {
-    let new_game_status = state.lock().unwrap().game.play_dealers_turn();
-
-    let new_game_data = state.lock().unwrap().game.clone();
-    Json(GameData::new(
-        new_game_data.dealer,
-        new_game_data.player,
-        new_game_data.bets,
-        new_game_data.shoe,
-        new_game_status,
-    ))
-}
-
-#[post("/end", data = "<message>")]
-pub fn end(state: &State<Arc<Mutex<GlobalState>>>, message: Json<Message<'_>>) -> Json<GameData> {
-    match message.action {
-        "PlayerWon" => state.lock().unwrap().game.end_game(GameStatus::PlayerWon),
-        "DealerWon" => state.lock().unwrap().game.end_game(GameStatus::DealerWon),
-        "Draw" => state.lock().unwrap().game.end_game(GameStatus::Draw),
-        _ => panic!("unknown command."),
-    }
-
-    // update player and shoe
-    let game_data = state.lock().unwrap().game.clone();
-    state.lock().unwrap().player = game_data.player.clone();
-    state.lock().unwrap().shoe = game_data.shoe.clone();
-
-    Json(GameData::new(
-        game_data.dealer,
-        game_data.player,
-        game_data.bets,
-        game_data.shoe,
-        GameStatus::Initalized,
-    )) // TODO: change GameStatus to actual status (currently just a placeholder)
+#[post("/<id>/action", data = "<message>")]
+pub fn session_action(
+    sessions: &State<Sessions>,
+    id: &str,
+    message: Json<Message<'_>>,
+) -> Result<Json<GameData>, ApiError> {
+    let action: Action = message
+        .action
+        .parse()
+        .map_err(|err: String| ApiError::new(Status::UnprocessableEntity, err))?;
+
+    sessions.with_game(id, |game| {
+        game.play_action(action)?;
+        Ok(Json(GameData::new(game)))
+    })
+}
+
+#[get("/<id>/simulateDealer")]
+pub fn session_simulate_dealer(
+    sessions: &State<Sessions>,
+    id: &str,
+) -> Result<Json<GameData>, ApiError> {
+    sessions.with_game(id, |game| {
+        game.play_dealers_turn()?;
+        Ok(Json(GameData::new(game)))
+    })
+}
+
+// outcomes are computed and paid out by the game itself, the client only moves on to the next round
+#[post("/<id>/end", data = "<claim>")]
+pub fn session_end(
+    sessions: &State<Sessions>,
+    id: &str,
+    claim: Option<Json<Message<'_>>>,
+) -> Result<Json<GameData>, ApiError> {
+    if claim.is_some() {
+        return Err(ApiError::new(
+            Status::BadRequest,
+            "outcomes are computed by the server, /end does not take a body",
+        ));
+    }
+
+    sessions.with_game(id, |game| {
+        game.end_game()?;
+        Ok(Json(GameData::new(game)))
+    })
+}
+
+// from the next round on the listed cards are dealt first, in the order they are drawn: player, dealer, player, the
+// hole card if the dealer peeks, then every card drawn after that
+#[post("/<id>/debug/shoe", data = "<shoe>")]
+pub fn session_debug_shoe(
+    sessions: &State<Sessions>,
+    id: &str,
+    shoe: Json<StackedShoe>,
+) -> Result<Json<GameData>, ApiError> {
+    let stack = cards::parse_hand(&shoe.cards)
+        .map_err(|err| ApiError::new(Status::UnprocessableEntity, err))?;
+    sessions.with_game(id, |game| {
+        let shoe = Shoe::stacked(&game.rules, &stack, Some(game.shoe.seed()))
+            .map_err(|err| ApiError::new(Status::UnprocessableEntity, err))?;
+        game.replace_shoe(shoe)?;
+        Ok(Json(GameData::new(game)))
+    })
 }
@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use crate::{blackjack::Game, player::Player, rules::RuleSet};
+
+// the session used by the routes without a session id, it never expires
+pub const DEFAULT_SESSION: &str = "default";
+
+#[derive(Debug, PartialEq, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub max_sessions: usize,
+    pub idle_timeout: u64, // seconds without a request until a session is removed
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            max_sessions: 100,
+            idle_timeout: 30 * 60,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SessionError {
+    NotFound(String),
+    LimitReached(usize),
+    Corrupted, // a request crashed while playing the session, its game starts over
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::NotFound(id) => write!(f, "session '{}' does not exist or expired", id),
+            SessionError::LimitReached(max) => {
+                write!(
+                    f,
+                    "the server is full, only {} sessions can be played at once",
+                    max
+                )
+            }
+            SessionError::Corrupted => {
+                write!(f, "the game state was corrupted, the game starts over")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+// a request locks only the game of its own session, requests of other sessions are not blocked by it
+struct Session {
+    game: Mutex<Game>,
+    last_used: Mutex<Instant>,
+}
+
+// every session plays its own game with its own player and shoe
+pub struct Sessions {
+    config: SessionConfig,
+    rules: RuleSet,
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+}
+
+impl Sessions {
+    pub fn new(config: SessionConfig, rules: RuleSet) -> Self {
+        let sessions = Sessions {
+            config,
+            rules,
+            sessions: Mutex::new(HashMap::new()),
+        };
+        sessions
+            .sessions()
+            .insert(String::from(DEFAULT_SESSION), sessions.new_session(None));
+        sessions
+    }
+
+    fn new_game(&self, seed: Option<u64>) -> Game {
+        Game::new(Player::new(10_000), self.rules, seed)
+    }
+
+    fn new_session(&self, seed: Option<u64>) -> Arc<Session> {
+        Arc::new(Session {
+            game: Mutex::new(self.new_game(seed)),
+            last_used: Mutex::new(Instant::now()),
+        })
+    }
+
+    // the map is only changed by single inserts and removals, so it is never left half changed by a crashed request
+    fn sessions(&self) -> MutexGuard<'_, HashMap<String, Arc<Session>>> {
+        self.sessions.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // start a new session and return its id, the seed of its shoe is random unless one is given
+    pub fn create(&self, seed: Option<u64>) -> Result<String, SessionError> {
+        let mut sessions = self.sessions();
+        self.remove_expired(&mut sessions);
+
+        // the default session does not count towards the limit
+        let user_sessions = sessions.len() - 1;
+        if user_sessions >= self.config.max_sessions {
+            return Err(SessionError::LimitReached(self.config.max_sessions));
+        }
+
+        let id = format!("{:032x}", rand::random::<u128>());
+        sessions.insert(id.clone(), self.new_session(seed));
+        Ok(id)
+    }
+
+    // remove a session and return the seed of its shoe, the game can be replayed with it once it is over
+    pub fn remove(&self, id: &str) -> Result<u64, SessionError> {
+        if id == DEFAULT_SESSION {
+            return Err(SessionError::NotFound(String::from(id)));
+        }
+        let session = self
+            .sessions()
+            .remove(id)
+            .ok_or_else(|| SessionError::NotFound(String::from(id)))?;
+
+        // a request still running on the session finishes first, the sessions are not locked meanwhile
+        let game = session.game.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(game.shoe.seed())
+    }
+
+    // run a request on the game of a session, the sessions are only locked to look the session up
+    pub fn with_game<T, E: From<SessionError>>(
+        &self,
+        id: &str,
+        request: impl FnOnce(&mut Game) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let session = {
+            let mut sessions = self.sessions();
+            self.remove_expired(&mut sessions);
+
+            let session = sessions
+                .get(id)
+                .ok_or_else(|| SessionError::NotFound(String::from(id)))?;
+            *session
+                .last_used
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+            Arc::clone(session)
+        };
+
+        let Ok(mut game) = session.game.lock() else {
+            // a request crashed in the middle of the round, the game starts over in a fresh session instead of
+            // staying broken (unless the session was already replaced or removed in the meantime)
+            let mut sessions = self.sessions();
+            if sessions
+                .get(id)
+                .is_some_and(|current| Arc::ptr_eq(current, &session))
+            {
+                sessions.insert(String::from(id), self.new_session(None));
+            }
+            return Err(SessionError::Corrupted.into());
+        };
+        request(&mut game)
+    }
+
+    fn remove_expired(&self, sessions: &mut HashMap<String, Arc<Session>>) {
+        let timeout = Duration::from_secs(self.config.idle_timeout);
+        sessions.retain(|id, session| {
+            id == DEFAULT_SESSION
+                || session
+                    .last_used
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .elapsed()
+                    < timeout
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+
+    fn sessions() -> Sessions {
+        Sessions::new(SessionConfig::default(), RuleSet::default())
+    }
+
+    #[test]
+    fn requests_of_other_sessions_are_not_blocked() {
+        let sessions = sessions();
+        let id = sessions.create(None).unwrap();
+
+        // with one lock for all sessions, the inner request would wait for the outer one forever
+        let balance = sessions.with_game(DEFAULT_SESSION, |_| {
+            sessions.with_game(&id, |game| Ok::<_, SessionError>(game.player.balance))
+        });
+        assert_eq!(balance, Ok(10_000));
+    }
+
+    #[test]
+    fn crashed_request_only_restarts_its_own_session() {
+        let sessions = sessions();
+        let id = sessions.create(Some(1)).unwrap();
+        sessions
+            .with_game(DEFAULT_SESSION, |game| {
+                game.player.balance = 5;
+                Ok::<_, SessionError>(())
+            })
+            .unwrap();
+
+        let crashed = panic::catch_unwind(AssertUnwindSafe(|| {
+            sessions.with_game(&id, |_| -> Result<(), SessionError> {
+                panic!("the request crashed")
+            })
+        }));
+        assert!(crashed.is_err());
+
+        let balance =
+            |id: &str| sessions.with_game(id, |game| Ok::<_, SessionError>(game.player.balance));
+        assert_eq!(balance(&id), Err(SessionError::Corrupted));
+        assert_eq!(balance(&id), Ok(10_000));
+        assert_eq!(balance(DEFAULT_SESSION), Ok(5));
+        assert!(sessions.create(None).is_ok());
+    }
+
+    #[test]
+    fn seed_is_returned_when_the_session_is_removed() {
+        let sessions = sessions();
+        let given = sessions.create(Some(42)).unwrap();
+        assert_eq!(sessions.remove(&given), Ok(42));
+        assert_eq!(
+            sessions.remove(&given),
+            Err(SessionError::NotFound(given.clone()))
+        );
+
+        let random = sessions.create(None).unwrap();
+        let seed = sessions
+            .with_game(&random, |game| Ok::<_, SessionError>(game.shoe.seed()))
+            .unwrap();
+        assert_eq!(sessions.remove(&random), Ok(seed));
+
+        assert!(sessions.remove(DEFAULT_SESSION).is_err());
+        assert!(sessions
+            .with_game(DEFAULT_SESSION, |_| Ok::<_, SessionError>(()))
+            .is_ok());
+    }
+
+    #[test]
+    fn default_session_does_not_count_towards_the_limit() {
+        let config = SessionConfig {
+            max_sessions: 2,
+            ..SessionConfig::default()
+        };
+        let sessions = Sessions::new(config, RuleSet::default());
+
+        let first = sessions.create(None).unwrap();
+        assert!(sessions.create(None).is_ok());
+        assert_eq!(sessions.create(None), Err(SessionError::LimitReached(2)));
+
+        sessions.remove(&first).unwrap();
+        assert!(sessions.create(None).is_ok());
+    }
+}
@@ -0,0 +1,134 @@
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+
+use crate::{
+    cards::{self, Card, Value},
+    rules::RuleSet,
+};
+
+// the cards the dealer deals from; once the cut card comes out, the shoe is shuffled before the next round
+#[derive(Debug, PartialEq, Serialize, Clone)]
+pub struct Shoe {
+    cards: Vec<Card>,     // the top of the shoe is the end of the vector
+    discards: Vec<Card>, // played and burned cards, they go back into the shoe with the next shuffle
+    remaining: [u32; 13], // cards left per rank, in the order of Value
+    number_of_decks: i8,
+    cut_card: usize, // number of cards behind the cut card
+    burn_cards: u8,
+    shuffles: u32, // how often the shoe was shuffled, a new shoe means a new count
+    seed: u64,
+    #[serde(skip)]
+    rng: ChaCha8Rng, // every shuffle comes from the seed, the same seed deals the same cards on every platform and version
+}
+
+impl Shoe {
+    // without a seed a random one is picked, it can be read with `seed()` to replay the game
+    pub fn new(rules: &RuleSet, seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(rand::random);
+        let mut shoe = Shoe {
+            cards: Vec::new(),
+            discards: cards::generate_shoe(rules.number_of_decks),
+            remaining: [0; 13],
+            number_of_decks: rules.number_of_decks,
+            cut_card: rules.cards_behind_cut_card(),
+            burn_cards: rules.burn_cards,
+            shuffles: 0,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        };
+        shoe.reshuffle();
+        shoe
+    }
+
+    // a shoe which deals the given cards first and in their order, e.g. to play a scenario. The cards are taken out of
+    // a full shoe, the rest of it follows shuffled.
+    pub fn stacked(rules: &RuleSet, stack: &[Card], seed: Option<u64>) -> Result<Self, String> {
+        let mut shoe = Shoe::new(rules, seed);
+        for card in stack {
+            // the burned cards can be stacked as well
+            if let Some(index) = shoe.cards.iter().position(|other| other == card) {
+                shoe.cards.remove(index);
+            } else if let Some(index) = shoe.discards.iter().position(|other| other == card) {
+                shoe.discards.remove(index);
+            } else {
+                return Err(format!("the shoe has no {} left to stack", card));
+            }
+        }
+
+        // the top of the shoe is the end of the vector
+        shoe.cards.extend(stack.iter().rev().cloned());
+        shoe.count_remaining();
+        Ok(shoe)
+    }
+
+    pub fn cards_remaining(&self) -> usize {
+        self.cards.len()
+    }
+
+    // cards of the rank left in the shoe
+    pub fn remaining(&self, value: Value) -> u32 {
+        self.remaining[value as usize]
+    }
+
+    pub fn number_of_decks(&self) -> i8 {
+        self.number_of_decks
+    }
+
+    pub fn shuffles(&self) -> u32 {
+        self.shuffles
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // the cut card came out, the next round is dealt from a shuffled shoe
+    pub fn needs_shuffle(&self) -> bool {
+        self.cards.len() <= self.cut_card
+    }
+
+    // the next card, without dealing it
+    pub fn peek(&self) -> Option<&Card> {
+        self.cards.last()
+    }
+
+    pub fn draw(&mut self) -> Card {
+        // the cards behind the cut card (at least MIN_CARDS_BEHIND_CUT_CARD) are enough for any realistic round. Only if
+        // a round still empties the shoe, the discards are shuffled in as a last resort.
+        if self.peek().is_none() {
+            self.reshuffle();
+        }
+
+        let card = self
+            .cards
+            .pop()
+            .expect("the cards on the table are never the whole shoe");
+        self.remaining[card.value as usize] -= 1;
+        card
+    }
+
+    // the cards of a finished round go to the discard tray
+    pub fn discard(&mut self, cards: Vec<Card>) {
+        self.discards.extend(cards);
+    }
+
+    // the discards are shuffled back into the shoe and the first cards are burned without being shown
+    pub fn reshuffle(&mut self) {
+        self.cards.append(&mut self.discards);
+        self.cards.shuffle(&mut self.rng);
+
+        let burned = self.cards.len().saturating_sub(self.burn_cards as usize);
+        self.discards = self.cards.split_off(burned);
+
+        self.count_remaining();
+        self.shuffles += 1;
+    }
+
+    fn count_remaining(&mut self) {
+        self.remaining = [0; 13];
+        for card in &self.cards {
+            self.remaining[card.value as usize] += 1;
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+
+use crate::{
+    blackjack::{Game, GameError},
+    counting::CountingSystem,
+    player::Player,
+    rules::RuleSet,
+    strategy::{self, Strategy},
+};
+
+// the simulated player is topped up to this balance before every round, so he never runs out of money
+const BANKROLL: i32 = 1_000_000_000;
+
+// z-score of a 95% confidence interval
+const Z_95: f64 = 1.96;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SimulationConfig {
+    pub rounds: u64,
+    pub rules: RuleSet,
+    pub counting: CountingSystem, // of the simulated player
+    pub seed: Option<u64>,        // of the shoe, a simulation with a seed can be repeated
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            rounds: 1_000_000,
+            rules: RuleSet::default(),
+            counting: CountingSystem::default(),
+            seed: None,
+        }
+    }
+}
+
+// results of a simulation, the expected value is measured in units of the initial bet of each round
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct SimulationReport {
+    pub rounds: u64,
+    pub hands: u64, // including the hands created by splitting
+    pub wins: u64,
+    pub losses: u64,
+    pub pushes: u64,
+    pub total_wagered: i64, // initial bets, doubles and splits
+    pub net_result: i64,
+    sum: f64,
+    sum_of_squares: f64,
+}
+
+impl SimulationReport {
+    fn record(&mut self, net: i32, bet: i32, hands: usize, wagered: i32) {
+        self.rounds += 1;
+        self.hands += hands as u64;
+        self.total_wagered += wagered as i64;
+        self.net_result += net as i64;
+
+        match net.signum() {
+            1 => self.wins += 1,
+            -1 => self.losses += 1,
+            _ => self.pushes += 1,
+        }
+
+        let units = net as f64 / bet as f64;
+        self.sum += units;
+        self.sum_of_squares += units * units;
+    }
+
+    // average result of a round
+    pub fn ev(&self) -> f64 {
+        if self.rounds == 0 {
+            return 0.0;
+        }
+        self.sum / self.rounds as f64
+    }
+
+    // standard deviation of the result of a round
+    pub fn std_dev(&self) -> f64 {
+        if self.rounds < 2 {
+            return 0.0;
+        }
+        let n = self.rounds as f64;
+        let variance = (self.sum_of_squares - self.sum * self.sum / n) / (n - 1.0);
+        variance.max(0.0).sqrt()
+    }
+
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        let margin = Z_95 * self.std_dev() / (self.rounds.max(1) as f64).sqrt();
+        (self.ev() - margin, self.ev() + margin)
+    }
+
+    pub fn win_rate(&self) -> f64 {
+        self.rate(self.wins)
+    }
+
+    pub fn loss_rate(&self) -> f64 {
+        self.rate(self.losses)
+    }
+
+    pub fn push_rate(&self) -> f64 {
+        self.rate(self.pushes)
+    }
+
+    fn rate(&self, count: u64) -> f64 {
+        if self.rounds == 0 {
+            return 0.0;
+        }
+        count as f64 / self.rounds as f64
+    }
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (low, high) = self.confidence_interval();
+        writeln!(f, "rounds:          {} ({} hands)", self.rounds, self.hands)?;
+        writeln!(f, "EV per round:    {:+.4}% of the bet", self.ev() * 100.0)?;
+        writeln!(
+            f,
+            "95% CI:          [{:+.4}%, {:+.4}%]",
+            low * 100.0,
+            high * 100.0
+        )?;
+        writeln!(f, "std. deviation:  {:.4} bets", self.std_dev())?;
+        writeln!(
+            f,
+            "won/lost/pushed: {:.2}% / {:.2}% / {:.2}%",
+            self.win_rate() * 100.0,
+            self.loss_rate() * 100.0,
+            self.push_rate() * 100.0
+        )?;
+        write!(
+            f,
+            "total wagered:   {} (net {:+})",
+            self.total_wagered, self.net_result
+        )
+    }
+}
+
+// play the configured number of rounds with the given strategy, the simulation stops early if the strategy leaves the table
+pub fn simulate(
+    config: SimulationConfig,
+    strategy: &mut dyn Strategy,
+) -> Result<SimulationReport, GameError> {
+    let mut game = Game::new(Player::new(BANKROLL), config.rules, config.seed);
+    game.player.stats.set_counting_system(config.counting);
+    let mut report = SimulationReport::default();
+
+    for _ in 0..config.rounds {
+        game.player.balance = BANKROLL;
+        let result = match strategy::play_round(&mut game, strategy)? {
+            Some(result) => result,
+            None => break,
+        };
+
+        report.record(result.net, result.bet, game.player.hands.len(), game.bets());
+    }
+
+    Ok(report)
+}
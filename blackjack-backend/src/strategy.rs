@@ -0,0 +1,203 @@
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    basic_strategy::BasicStrategy,
+    blackjack::{Action, Game, GameError, GameStatus, Phase},
+    cards::Card,
+    hint::{self, Hint},
+    player::Hand,
+    rules::RuleSet,
+};
+
+// everything a player at the table can see when he has to decide
+pub struct TableView<'a> {
+    pub hands: &'a [Hand],
+    pub active_hand: Option<usize>,
+    pub dealer_up_card: Option<&'a Card>,
+    pub balance: i32,
+    pub cards_remaining: usize,
+    pub running_count: f64, // of the counting system of the player
+    pub true_count: f64,
+    pub allowed_actions: Vec<Action>,
+    pub rules: RuleSet,
+    game: &'a Game, // only used for hints, which do not reveal the hole card or the shoe
+}
+
+impl<'a> TableView<'a> {
+    pub fn new(game: &'a Game) -> Self {
+        TableView {
+            hands: &game.player.hands,
+            active_hand: game.active_hand(),
+            dealer_up_card: game.dealer.up_card(),
+            balance: game.player.balance,
+            cards_remaining: game.shoe.cards_remaining(),
+            running_count: game.player.stats.counter().running_count(),
+            true_count: game.player.stats.counter().true_count(),
+            allowed_actions: game.allowed_actions(),
+            rules: game.rules,
+            game,
+        }
+    }
+
+    // the hand the player is deciding on
+    pub fn hand(&self) -> Option<&'a Hand> {
+        self.active_hand.map(|index| &self.hands[index])
+    }
+
+    pub fn is_allowed(&self, action: Action) -> bool {
+        self.allowed_actions.contains(&action)
+    }
+
+    // the basic strategy action and the EV of every allowed action
+    pub fn hint(&self) -> Result<Hint, GameError> {
+        hint::hint(self.game)
+    }
+}
+
+// a player sitting at the table, e.g. a human in the terminal or a bot in a simulation
+pub trait Strategy {
+    // bet size of the next round, None to leave the table
+    fn bet(&mut self, view: &TableView) -> Option<i32>;
+
+    // one of the allowed actions of the view
+    fn decide(&mut self, view: &TableView) -> Action;
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RoundResult {
+    pub status: GameStatus,
+    pub bet: i32, // initial bet
+    pub net: i32, // change of the balance, including doubles, splits and insurance
+}
+
+// play one round from the bet to the settlement; the settled round stays on the table until the next one starts
+pub fn play_round(
+    game: &mut Game,
+    strategy: &mut dyn Strategy,
+) -> Result<Option<RoundResult>, GameError> {
+    if game.phase == Phase::Settled {
+        game.end_game()?;
+    }
+
+    let bet = match strategy.bet(&TableView::new(game)) {
+        Some(bet) => bet,
+        None => return Ok(None),
+    };
+    let balance = game.player.balance;
+    game.start(bet)?;
+    finish_round(game, strategy)?;
+
+    Ok(Some(RoundResult {
+        status: game.status(),
+        bet,
+        net: game.player.balance - balance,
+    }))
+}
+
+// let the strategy play the started round until it is settled
+pub fn finish_round(game: &mut Game, strategy: &mut dyn Strategy) -> Result<(), GameError> {
+    loop {
+        match game.phase {
+            Phase::InsuranceOffered | Phase::PlayerTurn(_) => {
+                let action = strategy.decide(&TableView::new(game));
+                game.play_action(action)?;
+            }
+            Phase::DealerTurn => {
+                game.play_dealers_turn()?;
+            }
+            Phase::Settled => return Ok(()),
+            Phase::Betting => return Err(GameError::WrongPhase(Phase::Betting)),
+        }
+    }
+}
+
+// plays like the dealer: never insure, hit until 17 (and soft 17 if the dealer does) and never double, split or surrender
+pub struct MimicDealer {
+    pub bet: i32,
+}
+
+impl Strategy for MimicDealer {
+    fn bet(&mut self, _view: &TableView) -> Option<i32> {
+        Some(self.bet)
+    }
+
+    fn decide(&mut self, view: &TableView) -> Action {
+        let hand = match view.hand() {
+            Some(hand) => hand.evaluate(),
+            None => return Action::DeclineInsurance,
+        };
+
+        let hits_soft_17 = hand.total == 17 && hand.is_soft && view.rules.dealer_hits_soft_17;
+        if view.is_allowed(Action::Hit) && (hand.total < 17 || hits_soft_17) {
+            Action::Hit
+        } else {
+            Action::Stand
+        }
+    }
+}
+
+// raises the bet with the true count of the players counting system (hi-lo by default) and plays like the dealer otherwise
+pub struct CountingPlayer {
+    pub bet: i32,
+    pub spread: i32, // highest bet in units of the minimum bet
+}
+
+impl Strategy for CountingPlayer {
+    fn bet(&mut self, view: &TableView) -> Option<i32> {
+        let true_count = view.true_count.floor() as i32;
+        Some(self.bet * true_count.clamp(1, self.spread))
+    }
+
+    fn decide(&mut self, view: &TableView) -> Action {
+        MimicDealer { bet: self.bet }.decide(view)
+    }
+}
+
+// picks any of the allowed actions, a baseline every other strategy should beat
+pub struct RandomPlayer {
+    pub bet: i32,
+    rng: ChaCha8Rng,
+}
+
+impl RandomPlayer {
+    // with a seed the player makes the same decisions every time
+    pub fn new(bet: i32, seed: Option<u64>) -> Self {
+        RandomPlayer {
+            bet,
+            rng: seed.map_or_else(ChaCha8Rng::from_entropy, ChaCha8Rng::seed_from_u64),
+        }
+    }
+}
+
+impl Strategy for RandomPlayer {
+    fn bet(&mut self, _view: &TableView) -> Option<i32> {
+        Some(self.bet)
+    }
+
+    fn decide(&mut self, view: &TableView) -> Action {
+        *view
+            .allowed_actions
+            .choose(&mut self.rng)
+            .expect("the player can always stand or decline insurance")
+    }
+}
+
+// automated players which can be selected by name, e.g. on the command line
+pub fn by_name(
+    name: &str,
+    bet: i32,
+    rules: &RuleSet,
+    seed: Option<u64>,
+) -> Result<Box<dyn Strategy>, String> {
+    match name {
+        "basic" => Ok(Box::new(BasicStrategy::new(bet, rules))),
+        "mimic-dealer" => Ok(Box::new(MimicDealer { bet })),
+        "random" => Ok(Box::new(RandomPlayer::new(bet, seed))),
+        "hilo-counter" => Ok(Box::new(CountingPlayer { bet, spread: 8 })),
+        _ => Err(format!(
+            "unknown strategy '{}', expected basic, mimic-dealer, random or hilo-counter",
+            name
+        )),
+    }
+}